@@ -0,0 +1,58 @@
+use candle_core::Device;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use makemore_rs::bigrams::BigramModel;
+use makemore_rs::data::NameItem;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Number of names in the synthetic corpus, roughly the size of names.txt
+const CORPUS_SIZE: usize = 32_000;
+
+/// Number of names sampled per iteration of the sampling benchmark
+const SAMPLES_PER_ITER: u64 = 100;
+
+/// Builds a fixed corpus of random lowercase names so results don't depend on names.txt
+fn synthetic_corpus() -> Vec<NameItem> {
+    let mut rng = StdRng::seed_from_u64(42);
+    (0..CORPUS_SIZE)
+        .map(|_| {
+            let len = rng.gen_range(2..=10);
+            let name: String = (0..len)
+                .map(|_| rng.gen_range(b'a'..=b'z') as char)
+                .collect();
+            NameItem::new(name)
+        })
+        .collect()
+}
+
+fn bench_new(c: &mut Criterion) {
+    let names = synthetic_corpus();
+    let device = Device::Cpu;
+
+    let mut group = c.benchmark_group("bigram_new");
+    group.throughput(Throughput::Elements(names.len() as u64));
+    group.bench_function("synthetic_corpus", |b| {
+        b.iter(|| BigramModel::new(black_box(&names), &device).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_sample_name(c: &mut Criterion) {
+    let names = synthetic_corpus();
+    let model = BigramModel::new(&names, &Device::Cpu).unwrap();
+    let mut rng = StdRng::seed_from_u64(1337);
+
+    let mut group = c.benchmark_group("bigram_sample_name");
+    group.throughput(Throughput::Elements(SAMPLES_PER_ITER));
+    group.bench_function("synthetic_corpus", |b| {
+        b.iter(|| {
+            for _ in 0..SAMPLES_PER_ITER {
+                black_box(model.sample_name(&mut rng).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_new, bench_sample_name);
+criterion_main!(benches);
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use candle_core::{DType, Device};
+use candle_core::Device;
 use makemore_rs::bigrams::BigramModel;
 use makemore_rs::data::load_names_unique;
 use makemore_rs::plot::plot_bigram_heatmap;
@@ -12,10 +12,10 @@ fn main() -> Result<()> {
     let names = load_names_unique("./names.txt");
     let model = BigramModel::new(&names, &device)?;
 
-    let tensor = model.get_tensor().to_dtype(DType::F64)?;
+    let tensor = model.get_tensor();
     info!("Bigram counts: {:?}", tensor);
 
-    let bigram_map = tensor_to_bigram_hashmap(&tensor, model.get_chars())?;
+    let bigram_map = tensor_to_bigram_hashmap(tensor, model.get_chars())?;
     plot_bigram_heatmap(
         &bigram_map,
         model.get_chars(),
@@ -39,49 +39,40 @@ fn main() -> Result<()> {
     let model = BigramModel::new(&names, &device)?;
 
     info!("Generating names with bigram probabilities:");
+    let mut rng = rand::thread_rng();
     for _ in 0..5 {
-        let mut name = Vec::new();
-        #[allow(unused_assignments)]
-        let mut ix = 0;
-        let mut prev_ix = 0;
+        let (name, trace) = model.sample_name_with_trace(&mut rng)?;
+        // Split on vocabulary tokens so each pair lines up with a transition in the trace
+        let mut tokens = vec![".".to_string()];
+        tokens.extend(model.get_vocabulary().get_tokenizer().tokenize(&name));
+        tokens.push(".".to_string());
         let mut log_likelihood = 0.0;
 
         info!("New name:");
-        loop {
-            let probs = model.get_probabilities();
-            ix = model.multinomial(&probs, 1, true)?.to_vec1::<i64>()?[0] as usize
-                % model.get_vocabulary().get_size();
-
-            // Get the characters and probability
-            let ch1 = model.get_vocabulary().get_char(prev_ix);
-            let ch2 = model.get_vocabulary().get_char(ix);
-            let prob = probs.get(prev_ix)?.get(ix)?;
-            let logprob = prob.log()?;
-            log_likelihood += logprob.to_vec0::<f32>()?;
+        for (pair, prob) in tokens.windows(2).zip(&trace) {
+            let logprob = prob.ln();
+            log_likelihood += logprob;
             // Print the bigram and its probabilities
             info!(
                 "  {}{}: prob={:.4}, logprob={:.4}",
-                ch1,
-                ch2,
-                prob.to_vec0::<f32>()?,
-                logprob.to_vec0::<f32>()?
+                pair[0], pair[1], prob, logprob
             );
-
-            name.push(ch2);
-
-            if ix == 0 {
-                break;
-            }
-            prev_ix = ix;
         }
 
-        info!(
-            "Generated: {}",
-            name.iter().map(|c| c.as_str()).collect::<String>()
-        );
+        info!("Generated: {}", name);
         info!("Log likelihood: {}", log_likelihood);
         info!("---");
     }
 
+    info!(
+        "Log likelihood of the dataset: {:.4}",
+        model.log_likelihood(&names)?
+    );
+    info!("NLL of the dataset: {:.4}", model.nll(&names)?);
+    info!(
+        "Average NLL over the dataset: {:.4}",
+        model.normalized_nll(&names)?
+    );
+
     Ok(())
 }
@@ -14,23 +14,9 @@ fn main() -> Result<()> {
 
     // Generate 5 names
     info!("Generating names:");
+    let mut rng = rand::thread_rng();
     for _ in 0..5 {
-        let mut name = Vec::new();
-        #[allow(unused_assignments)]
-        let mut ix = 0;
-
-        loop {
-            let probs = model.get_probabilities();
-            ix = model.multinomial(&probs, 1, true)?.to_vec1::<i64>()?[0] as usize
-                % model.get_vocabulary().get_size();
-            name.push(model.get_vocabulary().get_char(ix));
-
-            if ix == 0 {
-                break;
-            }
-        }
-
-        info!("{}", name.iter().map(|c| c.as_str()).collect::<String>());
+        info!("{}", model.sample_name(&mut rng)?);
     }
 
     Ok(())
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use candle_core::{DType, Device};
+use candle_core::Device;
 use makemore_rs::bigrams::BigramModel;
 use makemore_rs::data::load_names_unique;
 use makemore_rs::plot::plot_bigram_heatmap;
@@ -11,11 +11,11 @@ fn main() -> Result<()> {
     let device = Device::Cpu;
     let names = load_names_unique("./names.txt");
     let model = BigramModel::new(&names, &device)?;
-    let tensor = model.get_tensor().to_dtype(DType::F64)?;
+    let tensor = model.get_tensor();
 
     info!("Bigram counts: {:?}", tensor);
     plot_bigram_heatmap(
-        &tensor_to_bigram_hashmap(&tensor, model.get_chars())?,
+        &tensor_to_bigram_hashmap(tensor, model.get_chars())?,
         model.get_chars(),
         model.get_vocabulary().get_char_to_idx(),
         "bigrams_tensor.png",
@@ -1,14 +1,92 @@
 //! Bigram language model implementation that tracks character pair frequencies
 //! and their probabilities in a given dataset.
 
+use crate::counts_from_weighted_pairs;
 use crate::data::NameItem;
+use crate::tokenizer::Tokenizer;
+use crate::utils::{mask_end_token, sample_from_probs};
 use crate::vocabulary::Vocabulary;
-use anyhow::Result;
+use crate::MakemoreError;
 use candle_core::{DType, Device, IndexOp, Tensor};
 use rand::Rng;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use tracing::debug;
 
+/// Maximum number of characters produced by [`BigramModel::generate_greedy`] and by
+/// [`BigramModel::sample_name_temperature`] below temperature 1, which would otherwise
+/// loop forever on a cycle of most likely transitions
+const MAX_GREEDY_LEN: usize = 20;
+
+/// Maximum number of characters produced by the stochastic samplers such as
+/// [`BigramModel::sample_name`], so a chain with a high-probability cycle still terminates
+pub(crate) const MAX_SAMPLE_LEN: usize = 50;
+
+/// Number of names each rayon task counts before its partial counts are merged
+#[cfg(feature = "rayon")]
+const PARALLEL_CHUNK_SIZE: usize = 1024;
+
+/// Tolerance used by [`BigramModel::validate`] when checking that rows sum to 1
+const ROW_SUM_TOLERANCE: f32 = 1e-4;
+
+/// Shading characters used by [`BigramModel::ascii_heatmap`], from zero to the largest count
+const ASCII_SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+/// What the probabilities of a context that never occurs in the counts are set to.
+///
+/// Normalizing such an all-zero count row would divide by zero, so it is handled
+/// explicitly instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyRowPolicy {
+    /// Leave the row at zero, so sampling from the context immediately ends the name
+    #[default]
+    Zeros,
+    /// Fill the row with a uniform distribution over the vocabulary
+    Uniform,
+}
+
+/// Character and length distributions of generated names compared with the training set,
+/// as returned by [`BigramModel::generation_stats`].
+///
+/// Divergences are in nats and measure the generated distribution against the training
+/// one. The KL divergence is infinite if names are generated with a length that never
+/// occurs in training; the Jensen-Shannon divergence is symmetric and bounded by `ln 2`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationStats {
+    /// Share of each character among all generated characters, in vocabulary order
+    /// without the '.' token
+    pub generated_chars: Vec<(String, f32)>,
+    /// Share of each character among all training characters, aligned with `generated_chars`
+    pub training_chars: Vec<(String, f32)>,
+    /// Share of generated names of each length, in characters
+    pub generated_lengths: BTreeMap<usize, f32>,
+    /// Share of training names of each length, in characters
+    pub training_lengths: BTreeMap<usize, f32>,
+    /// `KL(generated || training)` of the character distributions
+    pub char_kl: f32,
+    /// Jensen-Shannon divergence of the character distributions
+    pub char_js: f32,
+    /// `KL(generated || training)` of the length distributions
+    pub length_kl: f32,
+    /// Jensen-Shannon divergence of the length distributions
+    pub length_js: f32,
+}
+
+/// A sampled name together with how the model generated it, as returned by
+/// [`BigramModel::sample_record`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedName {
+    /// The generated name without boundary tokens
+    pub text: String,
+    /// Natural log-probability of the name under the model, the sum of the transition
+    /// log-probabilities
+    pub log_prob: f32,
+    /// Number of characters in `text`
+    pub length: usize,
+    /// Every transition taken, from the '.' start token to the '.' end token, with its
+    /// log-probability
+    pub transitions: Vec<(String, String, f32)>,
+}
+
 /// A statistical model that captures the frequencies and probabilities
 /// of character pairs (bigrams) in text data.
 #[derive(Debug, Clone)]
@@ -17,53 +95,2917 @@ pub struct BigramModel {
     counts: HashMap<(String, String), i32>,
     count_tensor: Tensor,
     probabilities: Tensor,
+    /// Set when counts changed since `probabilities` was last computed
+    stale: bool,
+    /// How contexts without any counts are normalized
+    empty_rows: EmptyRowPolicy,
+    /// Weighted number of training names of each length, in tokens
+    lengths: BTreeMap<usize, f32>,
 }
 
-impl BigramModel {
-    /// Creates a new BigramModel with computed frequencies and probabilities
+impl BigramModel {
+    /// Creates a new BigramModel with computed frequencies and probabilities
+    ///
+    /// Each bigram occurrence contributes the weight of the name it came from, so
+    /// heavily weighted names influence the model proportionally. The integer
+    /// `counts` map truncates fractional weighted counts.
+    ///
+    /// # Arguments
+    /// * `names` - Slice of name items used to build the vocabulary
+    /// * `device` - Device to store tensors on (CPU/GPU)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::{Device, IndexOp};
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// // "ab" is weighted far above "ba", so names almost always start with 'a'
+    /// let names = vec![NameItem::with_weight("ab", 100.0), NameItem::new("ba")];
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    ///
+    /// let a = model.get_vocabulary().get_char_to_idx()["a"];
+    /// let start_probs = model.get_probabilities().i(0)?.to_vec1::<f32>()?;
+    /// assert!(start_probs[a] > 0.99);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn new(names: &[NameItem], device: &Device) -> Result<Self, MakemoreError> {
+        let vocabulary = Vocabulary::new(names);
+
+        let pair_counts = Self::pair_counts(&vocabulary, names)?;
+        let lengths = Self::name_lengths(&vocabulary, names)?;
+
+        let count_tensor =
+            Self::pair_counts_to_tensor(&pair_counts, vocabulary.get_size(), device)?;
+        Self::from_counts(vocabulary, count_tensor, lengths)
+    }
+
+    /// Creates a new BigramModel, dropping rare bigrams before computing probabilities
+    ///
+    /// Any bigram whose (weighted) count is below `min_count` is zeroed out and the
+    /// remaining counts of its row are renormalized, which removes one-off transitions
+    /// that are mostly noise. The model uses [`EmptyRowPolicy::Uniform`], so a row left
+    /// with no counts at all after pruning falls back to a uniform distribution and
+    /// sampling from it still works, also after the probabilities are recomputed.
+    ///
+    /// # Arguments
+    /// * `names` - Slice of name items used to build the vocabulary
+    /// * `device` - Device to store tensors on (CPU/GPU)
+    /// * `min_count` - Minimum count a bigram needs to be kept
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let names = vec![
+    ///     NameItem::new("ab"),
+    ///     NameItem::new("ab"),
+    ///     NameItem::new("ab"),
+    ///     NameItem::new("ac"),
+    /// ];
+    /// let model = BigramModel::new_pruned(&names, &Device::Cpu, 2.0)?;
+    ///
+    /// assert_eq!(model.probability("a", "c")?, 0.0);
+    /// assert_eq!(model.probability("a", "b")?, 1.0);
+    /// for row in model.get_probabilities().to_vec2::<f32>()? {
+    ///     assert!((row.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn new_pruned(
+        names: &[NameItem],
+        device: &Device,
+        min_count: f32,
+    ) -> Result<Self, MakemoreError> {
+        let vocabulary = Vocabulary::new(names);
+
+        let mut pair_counts = Self::pair_counts(&vocabulary, names)?;
+        let lengths = Self::name_lengths(&vocabulary, names)?;
+
+        pair_counts.retain(|_, count| *count >= min_count);
+
+        let count_tensor =
+            Self::pair_counts_to_tensor(&pair_counts, vocabulary.get_size(), device)?;
+        Self::from_counts(vocabulary, count_tensor, lengths)?
+            .with_empty_rows(EmptyRowPolicy::Uniform)
+    }
+
+    /// Creates a new BigramModel over the tokens produced by `tokenizer`
+    ///
+    /// Registered multi-character tokens such as digraphs are counted as single units,
+    /// so e.g. "th" followed by "e" is one bigram. Every method that encodes names,
+    /// including sampling with a prefix and scoring names, uses the same tokenizer.
+    ///
+    /// # Arguments
+    /// * `names` - Slice of name items used to build the vocabulary
+    /// * `tokenizer` - Tokenizer used to split names into tokens
+    /// * `device` - Device to store tensors on (CPU/GPU)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::tokenizer::Tokenizer;
+    ///
+    /// let names = vec![NameItem::new("theo"), NameItem::new("seth")];
+    /// let model = BigramModel::new_with_tokenizer(&names, Tokenizer::new(&["th"]), &Device::Cpu)?;
+    ///
+    /// assert_eq!(model.count("th", "e")?, 1);
+    /// assert_eq!(model.count("e", "th")?, 1);
+    /// assert!(model.count("t", "h").is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn new_with_tokenizer(
+        names: &[NameItem],
+        tokenizer: Tokenizer,
+        device: &Device,
+    ) -> Result<Self, MakemoreError> {
+        let vocabulary = Vocabulary::with_tokenizer(names, tokenizer);
+
+        let pair_counts = Self::pair_counts(&vocabulary, names)?;
+        let lengths = Self::name_lengths(&vocabulary, names)?;
+
+        let count_tensor =
+            Self::pair_counts_to_tensor(&pair_counts, vocabulary.get_size(), device)?;
+        Self::from_counts(vocabulary, count_tensor, lengths)
+    }
+
+    /// Creates a new BigramModel whose vocabulary reserves an
+    /// [`UNK_TOKEN`](crate::vocabulary::UNK_TOKEN) slot
+    ///
+    /// The vocabulary is frozen at construction; afterwards characters that were not in
+    /// the training names are mapped to the unknown token wherever names are encoded, e.g.
+    /// by [`BigramModel::name_probability`] or [`BigramModel::add_name`], instead of
+    /// producing an error. Nothing follows the unknown token in the training data, so the
+    /// model uses [`EmptyRowPolicy::Uniform`] and its row is filled with a uniform
+    /// distribution, while transitions into it have probability 0 until names containing
+    /// unseen characters are added.
+    ///
+    /// # Arguments
+    /// * `names` - Slice of name items used to build the vocabulary
+    /// * `device` - Device to store tensors on (CPU/GPU)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::{Device, IndexOp};
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::vocabulary::UNK_TOKEN;
+    ///
+    /// let names = vec![NameItem::new("emma"), NameItem::new("ava")];
+    /// let mut model = BigramModel::new_with_unk(&names, &Device::Cpu)?;
+    /// assert_eq!(model.name_probability("emmz")?, 0.0);
+    ///
+    /// let unk = model.get_vocabulary().get_unk_index().unwrap();
+    /// let row = model.get_probabilities().i(unk)?.to_vec1::<f32>()?;
+    /// assert!(row.iter().all(|&p| (p - row[0]).abs() < 1e-6 && p > 0.0));
+    ///
+    /// model.add_name(&NameItem::new("emz"))?;
+    /// assert_eq!(model.count("m", UNK_TOKEN)?, 1);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn new_with_unk(names: &[NameItem], device: &Device) -> Result<Self, MakemoreError> {
+        let vocabulary = Vocabulary::with_unk(names);
+
+        let pair_counts = Self::pair_counts(&vocabulary, names)?;
+        let lengths = Self::name_lengths(&vocabulary, names)?;
+
+        let count_tensor =
+            Self::pair_counts_to_tensor(&pair_counts, vocabulary.get_size(), device)?;
+        Self::from_counts(vocabulary, count_tensor, lengths)?
+            .with_empty_rows(EmptyRowPolicy::Uniform)
+    }
+
+    /// Creates a new BigramModel over a given vocabulary instead of one inferred from
+    /// `names`
+    ///
+    /// With a fixed alphabet from [`Vocabulary::from_chars`] the count and probability
+    /// matrices always have the same dimensions, whichever characters occur in the data.
+    /// Characters that never occur have empty rows.
+    ///
+    /// # Arguments
+    /// * `names` - Slice of name items to count
+    /// * `vocabulary` - Vocabulary the names are encoded with
+    /// * `device` - Device to store tensors on (CPU/GPU)
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if a name contains a character outside the
+    ///   vocabulary
+    pub fn new_with_vocabulary(
+        names: &[NameItem],
+        vocabulary: Vocabulary,
+        device: &Device,
+    ) -> Result<Self, MakemoreError> {
+        let pair_counts = Self::pair_counts(&vocabulary, names)?;
+        let lengths = Self::name_lengths(&vocabulary, names)?;
+
+        let count_tensor =
+            Self::pair_counts_to_tensor(&pair_counts, vocabulary.get_size(), device)?;
+        Self::from_counts(vocabulary, count_tensor, lengths)
+    }
+
+    /// Sets how contexts without any counts are normalized and recomputes the
+    /// probabilities from the counts.
+    ///
+    /// Models start out with [`EmptyRowPolicy::Zeros`]. The policy is kept for later
+    /// recomputations, e.g. by [`BigramModel::recompute_probabilities`].
+    ///
+    /// # Arguments
+    /// * `policy` - How to fill rows whose counts are all zero
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::{Device, IndexOp};
+    /// use makemore_rs::bigrams::{BigramModel, EmptyRowPolicy};
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::vocabulary::Vocabulary;
+    ///
+    /// // 'z' is in the vocabulary but never occurs as a context
+    /// let vocabulary = Vocabulary::from_chars(&[".", "a", "b", "z"])?;
+    /// let model = BigramModel::new_with_vocabulary(&[NameItem::new("ab")], vocabulary, &Device::Cpu)?;
+    /// model.validate()?;
+    ///
+    /// let row = model.get_probabilities().i(3)?.to_vec1::<f32>()?;
+    /// assert!(row.iter().all(|&p| p == 0.0));
+    ///
+    /// let model = model.with_empty_rows(EmptyRowPolicy::Uniform)?;
+    /// model.validate()?;
+    /// let row = model.get_probabilities().i(3)?.to_vec1::<f32>()?;
+    /// assert!((row.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn with_empty_rows(mut self, policy: EmptyRowPolicy) -> Result<Self, MakemoreError> {
+        self.empty_rows = policy;
+        self.recompute_probabilities()?;
+        Ok(self)
+    }
+
+    /// Checks that every row of the probability tensor is a valid distribution.
+    ///
+    /// Rows that are entirely zero (contexts without counts under
+    /// [`EmptyRowPolicy::Zeros`]) are accepted; every other row must be finite and sum
+    /// to 1 within a small tolerance.
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidProbabilities`] listing the characters of all offending
+    ///   rows and their sums
+    pub fn validate(&self) -> Result<(), MakemoreError> {
+        let probabilities = self.probabilities.to_vec2::<f32>()?;
+        let offending: Vec<String> = probabilities
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| !row.iter().all(|&p| p == 0.0))
+            .filter_map(|(i, row)| {
+                let sum: f32 = row.iter().sum();
+                let valid = row.iter().all(|p| p.is_finite() && *p >= 0.0)
+                    && (sum - 1.0).abs() <= ROW_SUM_TOLERANCE;
+                (!valid).then(|| format!("{:?} (sum {})", self.vocabulary.get_char(i), sum))
+            })
+            .collect();
+
+        if !offending.is_empty() {
+            return Err(MakemoreError::InvalidProbabilities(format!(
+                "Rows are not normalized: {}",
+                offending.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Creates a new BigramModel with every bigram count clipped to `cap`
+    ///
+    /// Counts are capped as `min(count, cap)` before normalizing, which stops a few very
+    /// frequent pairs from dominating their rows and flattens each distribution toward
+    /// rarer but valid transitions. Both the count tensor and `counts` hold the capped
+    /// values.
+    ///
+    /// # Arguments
+    /// * `names` - Slice of name items used to build the vocabulary
+    /// * `device` - Device to store tensors on (CPU/GPU)
+    /// * `cap` - Maximum count kept for any bigram
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidArgument`] if `cap` is not positive
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let names: Vec<NameItem> = ["ab", "ab", "ab", "ab", "ac"]
+    ///     .iter()
+    ///     .map(|&name| NameItem::new(name))
+    ///     .collect();
+    /// let model = BigramModel::new_capped(&names, &Device::Cpu, 2.0)?;
+    ///
+    /// assert!(model.get_counts().values().all(|&count| count <= 2));
+    /// assert_eq!(model.probability("a", "b")?, 2.0 / 3.0);
+    /// for row in model.get_probabilities().to_vec2::<f32>()? {
+    ///     assert!((row.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn new_capped(
+        names: &[NameItem],
+        device: &Device,
+        cap: f32,
+    ) -> Result<Self, MakemoreError> {
+        if cap <= 0.0 {
+            return Err(MakemoreError::InvalidArgument(format!(
+                "Count cap must be positive, got {}",
+                cap
+            )));
+        }
+
+        let vocabulary = Vocabulary::new(names);
+
+        let mut pair_counts = Self::pair_counts(&vocabulary, names)?;
+        let lengths = Self::name_lengths(&vocabulary, names)?;
+
+        pair_counts
+            .values_mut()
+            .for_each(|count| *count = count.min(cap));
+
+        let count_tensor =
+            Self::pair_counts_to_tensor(&pair_counts, vocabulary.get_size(), device)?;
+        Self::from_counts(vocabulary, count_tensor, lengths)
+    }
+
+    /// Creates a new BigramModel, smoothing only the distribution of first characters
+    ///
+    /// `pseudo_count` is added to the count of every character following the '.' start
+    /// token, so any character can begin a name, while all other rows keep their raw
+    /// counts. Unlike global add-k smoothing this leaves the learned transitions inside
+    /// names untouched.
+    ///
+    /// # Arguments
+    /// * `names` - Slice of name items used to build the vocabulary
+    /// * `device` - Device to store tensors on (CPU/GPU)
+    /// * `pseudo_count` - Count added to each start transition
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidArgument`] if `pseudo_count` is negative
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let names = vec![NameItem::new("emma"), NameItem::new("ava")];
+    /// let raw = BigramModel::new(&names, &Device::Cpu)?.probabilities_matrix()?;
+    /// let smoothed =
+    ///     BigramModel::new_with_start_smoothing(&names, &Device::Cpu, 0.5)?.probabilities_matrix()?;
+    ///
+    /// // Only the start row changes, and every character can now begin a name
+    /// assert_ne!(smoothed[0], raw[0]);
+    /// assert!(smoothed[0][1..].iter().all(|&p| p > 0.0));
+    /// assert_eq!(smoothed[1..], raw[1..]);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn new_with_start_smoothing(
+        names: &[NameItem],
+        device: &Device,
+        pseudo_count: f32,
+    ) -> Result<Self, MakemoreError> {
+        if pseudo_count < 0.0 {
+            return Err(MakemoreError::InvalidArgument(format!(
+                "Pseudo-count must not be negative, got {}",
+                pseudo_count
+            )));
+        }
+
+        let vocabulary = Vocabulary::new(names);
+
+        let mut pair_counts = Self::pair_counts(&vocabulary, names)?;
+        let lengths = Self::name_lengths(&vocabulary, names)?;
+
+        for j in 1..vocabulary.get_size() {
+            *pair_counts.entry((0, j)).or_insert(0.0) += pseudo_count;
+        }
+
+        let count_tensor =
+            Self::pair_counts_to_tensor(&pair_counts, vocabulary.get_size(), device)?;
+        Self::from_counts(vocabulary, count_tensor, lengths)
+    }
+
+    /// Creates a new BigramModel that never repeats a character
+    ///
+    /// The diagonal of the count matrix (e.g. "a" followed by "a", or '.' followed by '.'
+    /// for empty names) is zeroed before normalizing, so generated names contain no
+    /// doubled letters. Each row is renormalized over its remaining transitions; a row
+    /// whose only transitions were self-transitions becomes empty and is handled by the
+    /// [`EmptyRowPolicy`]. Names added later with [`BigramModel::add_name`] can
+    /// reintroduce self-transitions.
+    ///
+    /// # Arguments
+    /// * `names` - Slice of name items used to build the vocabulary
+    /// * `device` - Device to store tensors on (CPU/GPU)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let names: Vec<NameItem> = ["anna", "emma", "otto", "ava", ""]
+    ///     .iter()
+    ///     .map(|&name| NameItem::new(name))
+    ///     .collect();
+    /// let model = BigramModel::new_without_self_transitions(&names, &Device::Cpu)?;
+    ///
+    /// for (i, row) in model.probabilities_matrix()?.iter().enumerate() {
+    ///     assert_eq!(row[i], 0.0);
+    ///     let sum: f32 = row.iter().sum();
+    ///     assert!(sum == 0.0 || (sum - 1.0).abs() < 1e-5);
+    /// }
+    /// assert_eq!(model.probability("n", "a")?, 1.0);
+    /// model.validate()?;
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn new_without_self_transitions(
+        names: &[NameItem],
+        device: &Device,
+    ) -> Result<Self, MakemoreError> {
+        let vocabulary = Vocabulary::new(names);
+
+        let mut pair_counts = Self::pair_counts(&vocabulary, names)?;
+        let lengths = Self::name_lengths(&vocabulary, names)?;
+
+        pair_counts.retain(|&(i, j), _| i != j);
+
+        let count_tensor =
+            Self::pair_counts_to_tensor(&pair_counts, vocabulary.get_size(), device)?;
+        Self::from_counts(vocabulary, count_tensor, lengths)
+    }
+
+    /// Accumulates the weighted count of every bigram in `names`, keyed by vocabulary indices.
+    ///
+    /// # Arguments
+    /// * `vocabulary` - Vocabulary used to encode the names
+    /// * `names` - Slice of name items to count
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if a name contains a character outside the
+    ///   vocabulary
+    pub fn count_pairs(
+        vocabulary: &Vocabulary,
+        names: &[NameItem],
+    ) -> Result<HashMap<(usize, usize), f32>, MakemoreError> {
+        let mut pair_counts = HashMap::new();
+        for name in names {
+            let tokens = Self::tokenize(vocabulary, &name.name)?;
+            for window in tokens.windows(2) {
+                *pair_counts.entry((window[0], window[1])).or_insert(0.0) += name.weight;
+            }
+        }
+        Ok(pair_counts)
+    }
+
+    /// Parallel version of [`BigramModel::count_pairs`] using rayon.
+    ///
+    /// Each thread counts a chunk of names into a local map, and the partial maps are
+    /// summed afterwards. [`BigramModel::new`] uses this when the `rayon` feature is enabled.
+    ///
+    /// # Arguments
+    /// * `vocabulary` - Vocabulary used to encode the names
+    /// * `names` - Slice of name items to count
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if a name contains a character outside the
+    ///   vocabulary
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::vocabulary::Vocabulary;
+    ///
+    /// let names: Vec<NameItem> = (0..5000)
+    ///     .map(|i| NameItem::new(["emma", "olivia", "ava", "isabella"][i % 4]))
+    ///     .collect();
+    /// let vocabulary = Vocabulary::new(&names);
+    ///
+    /// assert_eq!(
+    ///     BigramModel::count_pairs_parallel(&vocabulary, &names)?,
+    ///     BigramModel::count_pairs(&vocabulary, &names)?
+    /// );
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn count_pairs_parallel(
+        vocabulary: &Vocabulary,
+        names: &[NameItem],
+    ) -> Result<HashMap<(usize, usize), f32>, MakemoreError> {
+        use rayon::prelude::*;
+
+        let partials = names
+            .par_chunks(PARALLEL_CHUNK_SIZE)
+            .map(|chunk| Self::count_pairs(vocabulary, chunk))
+            .collect::<Result<Vec<_>, MakemoreError>>()?;
+
+        Ok(partials
+            .into_iter()
+            .fold(HashMap::new(), |mut pair_counts, partial| {
+                for (pair, count) in partial {
+                    *pair_counts.entry(pair).or_insert(0.0) += count;
+                }
+                pair_counts
+            }))
+    }
+
+    /// Creates a new BigramModel directly from in-memory words
+    ///
+    /// Each word becomes a [`NameItem`] with the default weight, which avoids writing
+    /// a file just to build a small model.
+    ///
+    /// # Arguments
+    /// * `words` - Words to build the model from
+    /// * `device` - Device to store tensors on (CPU/GPU)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["anna", "bob"], &Device::Cpu)?;
+    /// assert_eq!(model.get_counts()[&("n".to_string(), "n".to_string())], 1);
+    /// assert_eq!(model.get_counts()[&(".".to_string(), "b".to_string())], 1);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn from_words(words: &[&str], device: &Device) -> Result<Self, MakemoreError> {
+        let names: Vec<NameItem> = words.iter().map(|&word| NameItem::new(word)).collect();
+        Self::new(&names, device)
+    }
+
+    /// Merges two models by summing their count tensors and recomputing probabilities.
+    ///
+    /// This allows counts to be accumulated separately (e.g. one model per data shard)
+    /// and combined afterwards. If the models were built with different vocabularies,
+    /// the merged model uses their union and both count tensors are realigned to it
+    /// before summing.
+    ///
+    /// # Arguments
+    /// * `other` - Model to merge with this one
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let names = vec![NameItem::new("emma"), NameItem::new("ava")];
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    /// let merged = model.merge(&model)?;
+    ///
+    /// let counts = model.get_tensor().to_vec2::<f32>()?;
+    /// let merged_counts = merged.get_tensor().to_vec2::<f32>()?;
+    /// let probs = model.get_probabilities().to_vec2::<f32>()?;
+    /// let merged_probs = merged.get_probabilities().to_vec2::<f32>()?;
+    /// for i in 0..counts.len() {
+    ///     for j in 0..counts[i].len() {
+    ///         assert_eq!(merged_counts[i][j], 2.0 * counts[i][j]);
+    ///         assert!((merged_probs[i][j] - probs[i][j]).abs() < 1e-6);
+    ///     }
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn merge(&self, other: &BigramModel) -> Result<BigramModel, MakemoreError> {
+        let mut lengths = self.lengths.clone();
+        for (&len, &count) in &other.lengths {
+            *lengths.entry(len).or_insert(0.0) += count;
+        }
+
+        if self.vocabulary.get_chars() == other.vocabulary.get_chars() {
+            let count_tensor = (&self.count_tensor + &other.count_tensor)?;
+            return Self::from_counts(self.vocabulary.clone(), count_tensor, lengths)?
+                .with_empty_rows(self.empty_rows);
+        }
+
+        let (vocabulary, remap_self, remap_other) = self.vocabulary.union(&other.vocabulary);
+        let size = vocabulary.get_size();
+        let self_counts = Self::remap_counts(&self.count_tensor, &remap_self, size)?;
+        let other_counts = Self::remap_counts(&other.count_tensor, &remap_other, size)?;
+        Self::from_counts(vocabulary, (self_counts + other_counts)?, lengths)?
+            .with_empty_rows(self.empty_rows)
+    }
+
+    /// Adds the bigrams of a single name to the model's counts.
+    ///
+    /// Only `counts` and the count tensor are updated; the probabilities are marked as
+    /// stale until [`BigramModel::recompute_probabilities`] is called, so a batch of names
+    /// can be ingested without renormalizing after each one. The vocabulary is fixed at
+    /// construction.
+    ///
+    /// # Arguments
+    /// * `name` - Name item to add, contributing its weight to each of its bigrams
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if the name contains a character outside the
+    ///   vocabulary
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let mut model = BigramModel::from_words(&["emma", "ava"], &Device::Cpu)?;
+    /// assert_eq!(model.count("m", "m")?, 1);
+    ///
+    /// model.add_name(&NameItem::new("mma"))?;
+    /// assert_eq!(model.count("m", "m")?, 2);
+    /// assert_eq!(model.count(".", "m")?, 1);
+    /// assert!(model.is_stale());
+    ///
+    /// model.recompute_probabilities()?;
+    /// assert!(!model.is_stale());
+    /// assert_eq!(model.probability(".", "m")?, 1.0 / 3.0);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn add_name(&mut self, name: &NameItem) -> Result<(), MakemoreError> {
+        let tokens = Self::tokenize(&self.vocabulary, &name.name)?;
+        let mut pair_counts = HashMap::new();
+        for window in tokens.windows(2) {
+            *pair_counts.entry((window[0], window[1])).or_insert(0.0) += name.weight;
+        }
+        *self.lengths.entry(tokens.len() - 2).or_insert(0.0) += name.weight;
+
+        let size = self.vocabulary.get_size();
+        let delta = Self::pair_counts_to_tensor(&pair_counts, size, self.count_tensor.device())?;
+        self.count_tensor = (&self.count_tensor + delta)?;
+
+        let chars = self.vocabulary.get_chars();
+        for &(i, j) in pair_counts.keys() {
+            let count = self.count_tensor.i((i, j))?.to_scalar::<f32>()? as i32;
+            let pair = (chars[i].clone(), chars[j].clone());
+            if count > 0 {
+                self.counts.insert(pair, count);
+            } else {
+                self.counts.remove(&pair);
+            }
+        }
+
+        self.stale = true;
+        Ok(())
+    }
+
+    /// Renormalizes the count tensor into probabilities after calls to
+    /// [`BigramModel::add_name`].
+    pub fn recompute_probabilities(&mut self) -> Result<(), MakemoreError> {
+        self.probabilities = Self::compute_probabilities(&self.count_tensor, self.empty_rows)?;
+        self.stale = false;
+        Ok(())
+    }
+
+    /// Returns true if counts were added since the probabilities were last computed.
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    pub fn get_vocabulary(&self) -> &Vocabulary {
+        &self.vocabulary
+    }
+
+    pub fn get_counts(&self) -> &HashMap<(String, String), i32> {
+        &self.counts
+    }
+
+    /// Returns the non-zero bigram counts in a deterministic order.
+    ///
+    /// Pairs are sorted by descending count, with ties broken lexicographically by the
+    /// pair, so printed output and snapshot tests don't depend on `HashMap` iteration
+    /// order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "ava", "anna"], &Device::Cpu)?;
+    /// let sorted = model.counts_sorted();
+    ///
+    /// assert_eq!(sorted, model.counts_sorted());
+    /// assert_eq!(sorted[0], (("a".to_string(), ".".to_string()), 3));
+    /// assert_eq!(sorted[1], ((".".to_string(), "a".to_string()), 2));
+    /// assert!(sorted.windows(2).all(|w| w[0].1 >= w[1].1));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn counts_sorted(&self) -> Vec<((String, String), i32)> {
+        let mut counts: Vec<((String, String), i32)> = self
+            .counts
+            .iter()
+            .map(|(pair, &count)| (pair.clone(), count))
+            .collect();
+        counts.sort_by(|(pair_a, count_a), (pair_b, count_b)| {
+            count_b.cmp(count_a).then_with(|| pair_a.cmp(pair_b))
+        });
+        counts
+    }
+
+    /// Returns the 1-based rank of the bigram `a` followed by `b` among all non-zero
+    /// bigrams, ordered as in [`BigramModel::counts_sorted`].
+    ///
+    /// Pairs with equal counts get distinct ranks, ordered lexicographically by the pair.
+    ///
+    /// # Arguments
+    /// * `a` - The preceding character
+    /// * `b` - The following character
+    ///
+    /// # Returns
+    /// * The rank, or `None` if the pair was never counted
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "ava", "anna"], &Device::Cpu)?;
+    ///
+    /// assert_eq!(model.bigram_rank("a", "."), Some(1));
+    /// assert_eq!(model.bigram_rank(".", "a"), Some(2));
+    /// assert_eq!(model.bigram_rank("v", "m"), None);
+    /// assert_eq!(model.bigram_rank("z", "a"), None);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn bigram_rank(&self, a: &str, b: &str) -> Option<usize> {
+        self.counts_sorted()
+            .iter()
+            .position(|((x, y), _)| x == a && y == b)
+            .map(|ix| ix + 1)
+    }
+
+    /// Returns every vocabulary character with its number of occurrences, most frequent
+    /// first.
+    ///
+    /// Occurrences are counted as the second element of a bigram, so each character in
+    /// the training names is counted exactly once, and the count for '.' is the number
+    /// of names. Ties are ordered alphabetically.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["anna", "hannah", "ava"], &Device::Cpu)?;
+    /// let frequencies = model.chars_by_frequency()?;
+    ///
+    /// assert_eq!(frequencies[0], ("a".to_string(), 6));
+    /// assert_eq!(frequencies[1], ("n".to_string(), 4));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn chars_by_frequency(&self) -> Result<Vec<(String, i64)>, MakemoreError> {
+        let column_sums = self.count_tensor.sum(0)?.to_vec1::<f32>()?;
+        let mut frequencies: Vec<(String, i64)> = self
+            .vocabulary
+            .get_chars()
+            .iter()
+            .cloned()
+            .zip(column_sums.into_iter().map(|count| count as i64))
+            .collect();
+        frequencies.sort_by(|(char_a, count_a), (char_b, count_b)| {
+            count_b.cmp(count_a).then_with(|| char_a.cmp(char_b))
+        });
+        Ok(frequencies)
+    }
+
+    /// Returns the total number of bigram transitions counted by the model.
+    ///
+    /// This is the sum of all counts, including transitions to and from the '.' token.
+    /// Counts are read from the count tensor, so fractional weighted counts add up before
+    /// the total is rounded to the nearest integer, instead of being truncated one by one
+    /// as in [`BigramModel::get_counts`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "ava"], &Device::Cpu)?;
+    /// let summed: i64 = model.get_counts().values().map(|&c| c as i64).sum();
+    /// assert_eq!(model.total_transitions()?, summed);
+    /// assert_eq!(model.total_transitions()?, 9);
+    /// assert_eq!(model.get_vocabulary().get_total_occurrences(), 7);
+    ///
+    /// // The 4 transitions of "ava" weigh 1.5 each, which the integer counts map truncates
+    /// let names = vec![NameItem::with_weight("ava", 1.5), NameItem::new("emma")];
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    /// assert_eq!(model.total_transitions()?, 11);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn total_transitions(&self) -> Result<i64, MakemoreError> {
+        let total = self.count_tensor.sum_all()?.to_scalar::<f32>()?;
+        Ok(total.round() as i64)
+    }
+
+    /// Returns the fraction of the `vocab_size * vocab_size` possible transitions that
+    /// have a non-zero count.
+    ///
+    /// Low coverage signals a small or narrow dataset, where most bigrams are never seen.
+    /// Counts are read from the count tensor, so transitions seen only with fractional
+    /// weights or pseudo-counts are covered too.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// // ".ab." observes 3 of the 9 transitions between '.', 'a' and 'b'
+    /// let model = BigramModel::from_words(&["ab"], &Device::Cpu)?;
+    /// assert_eq!(model.get_vocabulary().get_size(), 3);
+    /// assert!((model.coverage()? - 1.0 / 3.0).abs() < 1e-6);
+    ///
+    /// // A weight below 1 still observes the transitions
+    /// let model = BigramModel::new(&[NameItem::with_weight("ab", 0.5)], &Device::Cpu)?;
+    /// assert!((model.coverage()? - 1.0 / 3.0).abs() < 1e-6);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn coverage(&self) -> Result<f32, MakemoreError> {
+        let counts = self.count_tensor.to_vec2::<f32>()?;
+        let possible = counts.iter().map(Vec::len).sum::<usize>();
+        if possible == 0 {
+            return Ok(0.0);
+        }
+        let observed = counts
+            .iter()
+            .flatten()
+            .filter(|&&count| count > 0.0)
+            .count();
+        Ok(observed as f32 / possible as f32)
+    }
+
+    pub fn get_chars(&self) -> &Vec<String> {
+        self.vocabulary.get_chars()
+    }
+
+    pub fn get_tensor(&self) -> &Tensor {
+        &self.count_tensor
+    }
+
+    pub fn get_probabilities(&self) -> &Tensor {
+        &self.probabilities
+    }
+
+    /// Returns the raw count of the bigram `a` followed by `b`.
+    ///
+    /// Unseen pairs of valid characters have a count of 0.
+    ///
+    /// # Arguments
+    /// * `a` - The preceding character
+    /// * `b` - The following character
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if either character is not in the vocabulary
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let names = vec![NameItem::new("emma"), NameItem::new("ava")];
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    ///
+    /// assert_eq!(model.count("m", "m")?, 1);
+    /// assert_eq!(model.count("v", "m")?, 0);
+    /// assert!(model.count("z", "a").is_err());
+    ///
+    /// assert_eq!(model.probability("v", "a")?, 1.0);
+    /// assert_eq!(model.probability("v", "m")?, 0.0);
+    /// assert!(model.probability("a", "z").is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn count(&self, a: &str, b: &str) -> Result<i32, MakemoreError> {
+        self.pair_indices(a, b)?;
+        Ok(self
+            .counts
+            .get(&(a.to_string(), b.to_string()))
+            .copied()
+            .unwrap_or(0))
+    }
+
+    /// Returns the probability of `b` following `a`.
+    ///
+    /// Unseen pairs of valid characters have a probability of 0.0.
+    ///
+    /// # Arguments
+    /// * `a` - The preceding character
+    /// * `b` - The following character
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if either character is not in the vocabulary
+    pub fn probability(&self, a: &str, b: &str) -> Result<f32, MakemoreError> {
+        let (i, j) = self.pair_indices(a, b)?;
+        Ok(self.probabilities.i((i, j))?.to_scalar::<f32>()?)
+    }
+
+    /// Returns the binomial standard error of the probability of `b` following `a`.
+    ///
+    /// Computed as `sqrt(p * (1 - p) / n)` where `n` is the total count of transitions out
+    /// of `a`. Probabilities estimated from sparse contexts have larger standard errors
+    /// and are less reliable.
+    ///
+    /// # Arguments
+    /// * `a` - The preceding character
+    /// * `b` - The following character
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if either character is not in the vocabulary
+    /// * [`MakemoreError::InvalidArgument`] if `a` never occurs as a context, leaving the
+    ///   probability undefined
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// // 'a' is seen eight times and 'x' twice, both split evenly between 'b' and 'c'
+    /// let words = ["ab", "ac", "ab", "ac", "ab", "ac", "ab", "ac", "xb", "xc"];
+    /// let model = BigramModel::from_words(&words, &Device::Cpu)?;
+    ///
+    /// assert!(model.probability_stderr("a", "b")? < model.probability_stderr("x", "b")?);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn probability_stderr(&self, a: &str, b: &str) -> Result<f32, MakemoreError> {
+        let (i, j) = self.pair_indices(a, b)?;
+        let n = self.count_tensor.i(i)?.sum_all()?.to_scalar::<f32>()?;
+        if n <= 0.0 {
+            return Err(MakemoreError::InvalidArgument(format!(
+                "Character {:?} never occurs as a context",
+                a
+            )));
+        }
+
+        let p = self.probabilities.i((i, j))?.to_scalar::<f32>()?;
+        Ok((p * (1.0 - p) / n).sqrt())
+    }
+
+    /// Returns the probability tensor as a dense `[vocab][vocab]` grid.
+    ///
+    /// Row `i` and column `j` correspond to `get_chars()[i]` and `get_chars()[j]`, so
+    /// `matrix[i][j]` is the probability of character `j` following character `i`. This
+    /// gives plain access to the probabilities for custom visualization or export.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "ava", "anna"], &Device::Cpu)?;
+    /// let matrix = model.probabilities_matrix()?;
+    ///
+    /// assert_eq!(matrix.len(), model.get_chars().len());
+    /// for (row, counts) in matrix.iter().zip(model.get_tensor().to_vec2::<f32>()?) {
+    ///     if counts.iter().sum::<f32>() > 0.0 {
+    ///         assert!((row.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+    ///     }
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn probabilities_matrix(&self) -> Result<Vec<Vec<f32>>, MakemoreError> {
+        Ok(self.probabilities.to_vec2::<f32>()?)
+    }
+
+    pub fn get_probabilities_map(&self) -> Option<HashMap<(String, String), f32>> {
+        let probabilities = &self.probabilities;
+        let chars = self.vocabulary.get_chars();
+        probabilities.to_dtype(DType::F32).ok().and_then(|p| {
+            p.to_dtype(DType::F32)
+                .ok()?
+                .to_vec2::<f32>()
+                .ok()
+                .map(|data| {
+                    data.iter()
+                        .enumerate()
+                        .flat_map(|(i, row)| {
+                            row.iter()
+                                .enumerate()
+                                .map(move |(j, &v)| ((chars[i].clone(), chars[j].clone()), v))
+                        })
+                        .collect()
+                })
+        })
+    }
+
+    /// Returns how far each transition probability departs from a uniform distribution.
+    ///
+    /// Every entry is `p(b | a) - 1 / vocab_size`: positive for transitions the data
+    /// favors and negative for those it disfavors. The deviations of a normalized row sum
+    /// to 0. To visualize them, pass the map to [`crate::plot::plot_bigram_diff`] together
+    /// with an empty map, which its diverging colors render red for favored and blue for
+    /// disfavored transitions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "ava", "anna"], &Device::Cpu)?;
+    /// let deviation = model.deviation_from_uniform()?;
+    /// let chars = model.get_chars();
+    ///
+    /// for a in chars {
+    ///     let row_sum: f32 = chars.iter().map(|b| deviation[&(a.clone(), b.clone())]).sum();
+    ///     assert!(row_sum.abs() < 1e-5);
+    /// }
+    /// assert!(deviation[&("e".to_string(), "m".to_string())] > 0.0);
+    /// assert!(deviation[&("e".to_string(), "v".to_string())] < 0.0);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn deviation_from_uniform(&self) -> Result<HashMap<(String, String), f32>, MakemoreError> {
+        let uniform = 1.0 / self.vocabulary.get_size() as f32;
+        let chars = self.vocabulary.get_chars();
+        Ok(self
+            .probabilities
+            .to_vec2::<f32>()?
+            .iter()
+            .enumerate()
+            .flat_map(|(i, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(move |(j, &p)| ((chars[i].clone(), chars[j].clone()), p - uniform))
+            })
+            .collect())
+    }
+
+    /// Samples indices from a probability distribution using the multinomial distribution.
+    ///
+    /// # Arguments
+    /// * `probs` - Tensor containing probabilities
+    /// * `num_samples` - Number of samples to draw
+    /// * `replacement` - Whether to sample with replacement
+    ///
+    /// # Returns
+    /// * Tensor containing sampled indices
+    pub fn multinomial(
+        &self,
+        probs: &Tensor,
+        num_samples: i64,
+        replacement: bool,
+    ) -> Result<Tensor, MakemoreError> {
+        let device = probs.device();
+        let mut p = if probs.dims().len() > 1 {
+            debug!(
+                "Flattening probabilities tensor of shape {:?}",
+                probs.dims()
+            );
+            let flat = probs.flatten_all()?.to_vec1::<f32>()?;
+            // Normalize the flattened probabilities
+            let sum: f32 = flat.iter().sum();
+            flat.iter().map(|&x| x / sum).collect::<Vec<_>>()
+        } else {
+            let p = probs.to_vec1::<f32>()?;
+            let sum: f32 = p.iter().sum();
+            p.iter().map(|&x| x / sum).collect::<Vec<_>>()
+        };
+
+        debug!(
+            "Initial probability distribution sum: {}",
+            p.iter().sum::<f32>()
+        );
+
+        let mut samples = Vec::with_capacity(num_samples as usize);
+        let mut rng = rand::thread_rng();
+
+        for sample_idx in 0..num_samples {
+            // Recompute cumulative probabilities each time
+            let mut cumulative = vec![0.0; p.len()];
+            let mut sum = 0.0;
+            for (i, &prob) in p.iter().enumerate() {
+                sum += prob;
+                cumulative[i] = sum;
+            }
+            debug!("Sample {}: Cumulative sum: {}", sample_idx, sum);
+
+            let r: f32 = rng.gen::<f32>();
+            debug!("Sample {}: Random value: {}", sample_idx, r);
+
+            let selected_idx =
+                match cumulative.binary_search_by(|&cum| cum.partial_cmp(&r).unwrap()) {
+                    Ok(idx) => idx,
+                    Err(idx) => idx,
+                };
+
+            debug!(
+                "Sample {}: Selected index: {}, Probability: {}",
+                sample_idx, selected_idx, p[selected_idx]
+            );
+            samples.push(selected_idx as i64);
+
+            if !replacement {
+                // Zero out the selected probability and renormalize
+                p[selected_idx] = 0.0;
+                let new_sum: f32 = p.iter().sum();
+                if new_sum > 0.0 {
+                    for prob in p.iter_mut() {
+                        *prob /= new_sum;
+                    }
+                    debug!(
+                        "Sample {}: Renormalized distribution sum: {}",
+                        sample_idx,
+                        p.iter().sum::<f32>()
+                    );
+                }
+            }
+        }
+
+        debug!("Final samples: {:?}", samples);
+        Ok(Tensor::new(samples.as_slice(), device)?)
+    }
+
+    /// Samples an index from a probability row restricted to its `k` most likely entries.
+    ///
+    /// All but the `k` highest-probability characters are zeroed and the remainder is
+    /// renormalized before sampling. When `k` is at least the row length this is the
+    /// same as sampling from the full row.
+    ///
+    /// # Arguments
+    /// * `probs_row` - 1-D tensor of next-character probabilities
+    /// * `k` - Number of most likely characters to keep
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * Index of the sampled character
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidArgument`] if `k` is 0, which would leave nothing to
+    ///   sample
+    /// * [`MakemoreError::InvalidProbabilities`] if the `k` kept entries have no mass
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::{Device, IndexOp};
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let names = vec![NameItem::new("ab"), NameItem::new("ab"), NameItem::new("ac")];
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    /// let char_to_idx = model.get_vocabulary().get_char_to_idx();
+    ///
+    /// // With k = 1 the most likely successor of 'a' is always chosen
+    /// let row = model.get_probabilities().i(char_to_idx["a"])?;
+    /// let mut rng = rand::thread_rng();
+    /// for _ in 0..20 {
+    ///     assert_eq!(model.sample_top_k(&row, 1, &mut rng)?, char_to_idx["b"]);
+    /// }
+    /// assert!(model.sample_top_k(&row, 0, &mut rng).is_err());
+    ///
+    /// // A row without any mass cannot be sampled from
+    /// assert!(model.sample_top_k(&row.zeros_like()?, 3, &mut rng).is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn sample_top_k<R: Rng + ?Sized>(
+        &self,
+        probs_row: &Tensor,
+        k: usize,
+        rng: &mut R,
+    ) -> Result<usize, MakemoreError> {
+        if k == 0 {
+            return Err(MakemoreError::InvalidArgument(
+                "Top-k sampling needs k of at least 1".to_string(),
+            ));
+        }
+        let mut p = probs_row.to_vec1::<f32>()?;
+
+        if k < p.len() {
+            let mut order: Vec<usize> = (0..p.len()).collect();
+            order.sort_by(|&a, &b| p[b].total_cmp(&p[a]));
+            for &ix in &order[k..] {
+                p[ix] = 0.0;
+            }
+        }
+        debug!("Top-{} probabilities: {:?}", k, p);
+
+        sample_from_probs(&p, rng)
+    }
+
+    /// Samples a single name by walking the bigram chain from the '.' start token.
+    ///
+    /// At each step the next character is drawn from the probability row of the previous
+    /// character, until the '.' end token is sampled. The end token is masked out of the
+    /// first step, so the name is never empty unless the start row has no probability
+    /// mass on any character. Names are cut off after 50 characters, so a chain that
+    /// keeps cycling between likely transitions still terminates.
+    ///
+    /// # Arguments
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * The generated name without boundary tokens
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidProbabilities`] if a probability row reached while sampling
+    ///   has no mass or holds a negative or NaN value
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// // Nine empty names put 90% of the start row on the '.' -> '.' transition
+    /// let mut words = vec![""; 9];
+    /// words.push("a");
+    /// let model = BigramModel::from_words(&words, &Device::Cpu)?;
+    ///
+    /// let mut rng = rand::thread_rng();
+    /// for _ in 0..20 {
+    ///     assert_eq!(model.sample_name(&mut rng)?, "a");
+    /// }
+    ///
+    /// // 'a' is followed by another 'a' 999 times out of 1000
+    /// let word = "a".repeat(1000);
+    /// let model = BigramModel::from_words(&[word.as_str()], &Device::Cpu)?;
+    /// assert!(model.sample_name(&mut rng)?.chars().count() <= 50);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn sample_name<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<String, MakemoreError> {
+        self.generate_iter(rng)?.collect()
+    }
+
+    /// Returns an iterator that generates a name one character at a time.
+    ///
+    /// Each call to `next` samples the following character from the probability row of
+    /// the previous one, and the iterator ends once the '.' end token is sampled. As in
+    /// [`BigramModel::sample_name`], the end token cannot be drawn on the first step and
+    /// the iterator yields at most 50 characters. This allows streaming output or
+    /// stopping generation early.
+    ///
+    /// # Arguments
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * An iterator over the characters of the generated name, without boundary tokens.
+    ///   If a probability row reached while sampling cannot be sampled from, it yields
+    ///   [`MakemoreError::InvalidProbabilities`] and ends.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use rand::rngs::StdRng;
+    /// use rand::SeedableRng;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "olivia", "ava"], &Device::Cpu)?;
+    ///
+    /// for seed in 0..10 {
+    ///     let streamed = model
+    ///         .generate_iter(&mut StdRng::seed_from_u64(seed))?
+    ///         .collect::<Result<String, _>>()?;
+    ///     let sampled = model.sample_name(&mut StdRng::seed_from_u64(seed))?;
+    ///     assert_eq!(streamed, sampled);
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn generate_iter<'a, R: Rng + ?Sized>(
+        &'a self,
+        rng: &'a mut R,
+    ) -> Result<impl Iterator<Item = Result<String, MakemoreError>> + 'a, MakemoreError> {
+        let mut probabilities = self.probabilities.to_vec2::<f32>()?;
+        // The start row is only used for the first step, so masking it once is enough
+        mask_end_token(&mut probabilities[0], 0, 0);
+        // `None` once the end token has been sampled or sampling has failed
+        let mut ix = Some(0);
+
+        Ok(
+            std::iter::from_fn(move || match sample_from_probs(&probabilities[ix?], rng) {
+                Ok(0) => {
+                    ix = None;
+                    None
+                }
+                Ok(next) => {
+                    ix = Some(next);
+                    Some(Ok(self.vocabulary.get_char(next).clone()))
+                }
+                Err(e) => {
+                    ix = None;
+                    Some(Err(e))
+                }
+            })
+            .take(MAX_SAMPLE_LEN),
+        )
+    }
+
+    /// Samples a single name and records its score and the transitions that produced it.
+    ///
+    /// The name is sampled exactly as by [`BigramModel::sample_name`], so the same rng
+    /// state yields the same text. Log-probabilities are taken from the model's
+    /// probability matrix, including the final transition to the '.' end token.
+    ///
+    /// # Arguments
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use rand::rngs::StdRng;
+    /// use rand::SeedableRng;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "olivia", "ava"], &Device::Cpu)?;
+    ///
+    /// for seed in 0..10 {
+    ///     let record = model.sample_record(&mut StdRng::seed_from_u64(seed))?;
+    ///     assert_eq!(record.text, model.sample_name(&mut StdRng::seed_from_u64(seed))?);
+    ///     assert_eq!(record.length, record.text.chars().count());
+    ///     assert_eq!(record.transitions.len(), record.length + 1);
+    ///
+    ///     let summed: f32 = record.transitions.iter().map(|(_, _, lp)| lp).sum();
+    ///     assert!((record.log_prob - summed).abs() < 1e-5);
+    ///     assert!((record.log_prob - model.name_log_probability(&record.text)?).abs() < 1e-5);
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn sample_record<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+    ) -> Result<GeneratedName, MakemoreError> {
+        let tokens = self.generate_iter(rng)?.collect::<Result<Vec<_>, _>>()?;
+        let probabilities = self.probabilities.to_vec2::<f32>()?;
+        let chars = self.vocabulary.get_chars();
+        let char_to_idx = self.vocabulary.get_char_to_idx();
+
+        let mut transitions = Vec::with_capacity(tokens.len() + 1);
+        let mut prev = 0;
+        for ix in tokens
+            .iter()
+            .map(|token| char_to_idx[token])
+            .chain(std::iter::once(0))
+        {
+            let log_prob = probabilities[prev][ix].ln();
+            transitions.push((chars[prev].clone(), chars[ix].clone(), log_prob));
+            prev = ix;
+        }
+
+        let text = tokens.concat();
+        Ok(GeneratedName {
+            log_prob: transitions.iter().map(|(_, _, log_prob)| log_prob).sum(),
+            length: text.chars().count(),
+            text,
+            transitions,
+        })
+    }
+
+    /// Samples a single name whose length lies within `[min_len, max_len]`.
+    ///
+    /// Until `min_len` characters have been generated the '.' end token is suppressed by
+    /// zeroing its probability before sampling; as in [`BigramModel::sample_name`] it is
+    /// always suppressed on the first step. Once `max_len` characters have been
+    /// generated the name is terminated. If a character is only ever followed by the end
+    /// token, suppression leaves nothing to sample and the name ends early.
+    ///
+    /// # Arguments
+    /// * `min_len` - Minimum number of characters
+    /// * `max_len` - Maximum number of characters
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * The generated name without boundary tokens
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let names = vec![NameItem::new("abc"), NameItem::new("bca"), NameItem::new("cab")];
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    /// let mut rng = rand::thread_rng();
+    ///
+    /// for _ in 0..20 {
+    ///     let name = model.sample_name_bounded(3, 5, &mut rng)?;
+    ///     assert!((3..=5).contains(&name.chars().count()), "{}", name);
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn sample_name_bounded<R: Rng + ?Sized>(
+        &self,
+        min_len: usize,
+        max_len: usize,
+        rng: &mut R,
+    ) -> Result<String, MakemoreError> {
+        let walk = Self::sample_walk(&self.probabilities, &self.vocabulary, min_len, max_len, rng)?;
+        Ok(walk.0)
+    }
+
+    /// Samples a single name after sharpening or flattening each probability row with a
+    /// temperature.
+    ///
+    /// Every row is raised to the power `1 / temperature` and renormalized, so values below
+    /// 1 favour likely transitions and values above 1 move towards uniform over the
+    /// transitions seen in training. As in [`BigramModel::sample_name`] the end token cannot
+    /// be drawn on the first step. As the temperature approaches 0 sampling approaches
+    /// [`BigramModel::generate_greedy`]; like it, names sampled below temperature 1 are cut
+    /// off after 20 characters because a cold chain can get stuck in a cycle. At
+    /// temperature 1 and above they are cut off after 50 characters like
+    /// [`BigramModel::sample_name`], so temperature 1 samples the raw probabilities.
+    ///
+    /// # Arguments
+    /// * `temperature` - Positive sampling temperature, 1 samples the raw probabilities
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * The generated name without boundary tokens
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidArgument`] if `temperature` is not a positive finite
+    ///   number
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// // 'a' is followed by 'b' three times as often as by 'c'
+    /// let model = BigramModel::from_words(&["ab", "ab", "ab", "ac"], &Device::Cpu)?;
+    /// let mut rng = rand::thread_rng();
+    ///
+    /// // At a very low temperature the most probable transition always wins
+    /// for _ in 0..50 {
+    ///     assert_eq!(model.sample_name_temperature(0.01, &mut rng)?, "ab");
+    /// }
+    /// assert!(model.sample_name_temperature(0.0, &mut rng).is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn sample_name_temperature<R: Rng + ?Sized>(
+        &self,
+        temperature: f32,
+        rng: &mut R,
+    ) -> Result<String, MakemoreError> {
+        if !(temperature.is_finite() && temperature > 0.0) {
+            return Err(MakemoreError::InvalidArgument(format!(
+                "Temperature must be positive, got {}",
+                temperature
+            )));
+        }
+
+        // Scale in log space so tiny temperatures don't underflow every weight to zero
+        let probabilities: Vec<Vec<f32>> = self
+            .probabilities
+            .to_vec2::<f32>()?
+            .into_iter()
+            .map(|row| {
+                let max = row
+                    .iter()
+                    .filter(|&&p| p > 0.0)
+                    .fold(f32::NEG_INFINITY, |max, &p| max.max(p.ln()));
+                row.iter()
+                    .map(|&p| {
+                        if p > 0.0 {
+                            ((p.ln() - max) / temperature).exp()
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let max_len = if temperature < 1.0 {
+            MAX_GREEDY_LEN
+        } else {
+            MAX_SAMPLE_LEN
+        };
+        let mut name = String::new();
+        let mut ix = 0;
+        for len in 0..max_len {
+            let mut weights = probabilities[ix].clone();
+            mask_end_token(&mut weights, len, 0);
+            ix = sample_from_probs(&weights, rng)?;
+            if ix == 0 {
+                break;
+            }
+            name.push_str(self.vocabulary.get_char(ix));
+        }
+
+        Ok(name)
+    }
+
+    /// Samples a single name along with the probability of each chosen transition.
+    ///
+    /// The trace has one entry per sampled transition, starting with the transition out of
+    /// the '.' start token and ending with the transition into the '.' end token, so it is
+    /// one longer than the generated name in vocabulary tokens. As in [`BigramModel::sample_name`] the end
+    /// token cannot be drawn on the first step and the name is cut off after 50
+    /// characters, in which case the trace has no final transition. The trace still
+    /// records the unmasked probability of each transition.
+    ///
+    /// # Arguments
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * The generated name and the probabilities of its transitions
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::tokenizer::Tokenizer;
+    ///
+    /// // "th" is a single token, so "thea" takes four transitions rather than five
+    /// let names = vec![NameItem::new("thea"), NameItem::new("beth"), NameItem::new("theo")];
+    /// let model = BigramModel::new_with_tokenizer(&names, Tokenizer::new(&["th"]), &Device::Cpu)?;
+    /// let mut rng = rand::thread_rng();
+    ///
+    /// let (name, trace) = model.sample_name_with_trace(&mut rng)?;
+    /// assert_eq!(trace.len(), model.get_vocabulary().encode(&name)?.len() + 1);
+    /// assert!(trace.iter().all(|&p| p > 0.0 && p <= 1.0));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn sample_name_with_trace<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+    ) -> Result<(String, Vec<f32>), MakemoreError> {
+        Self::sample_walk(
+            &self.probabilities,
+            &self.vocabulary,
+            0,
+            MAX_SAMPLE_LEN,
+            rng,
+        )
+    }
+
+    /// Samples a name that starts with `prefix`.
+    ///
+    /// The prefix is taken as already generated, so sampling continues from its last
+    /// character as if the chain had walked through it. An empty prefix behaves like
+    /// [`BigramModel::sample_name`], so the end token cannot be drawn first and the name
+    /// is never empty. Sampling stops once the name, prefix included, reaches 50
+    /// characters, so a prefix of that length or longer is returned unchanged.
+    ///
+    /// # Arguments
+    /// * `prefix` - Characters the name must start with
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * The prefix followed by the sampled continuation, without boundary tokens
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if the prefix contains a character outside the
+    ///   vocabulary
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["kate", "kara", "mia"], &Device::Cpu)?;
+    /// let mut rng = rand::thread_rng();
+    ///
+    /// for _ in 0..20 {
+    ///     assert!(model.sample_with_prefix("ka", &mut rng)?.starts_with("ka"));
+    /// }
+    /// assert!(model.sample_with_prefix("kz", &mut rng).is_err());
+    ///
+    /// // 'a' is followed by another 'a' 999 times out of 1000
+    /// let word = "a".repeat(1000);
+    /// let model = BigramModel::from_words(&[word.as_str()], &Device::Cpu)?;
+    /// assert!(model.sample_with_prefix("a", &mut rng)?.chars().count() <= 50);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn sample_with_prefix<R: Rng + ?Sized>(
+        &self,
+        prefix: &str,
+        rng: &mut R,
+    ) -> Result<String, MakemoreError> {
+        let mut name = prefix.to_string();
+        let encoded = self.vocabulary.encode(prefix)?;
+        let mut len = encoded.len();
+        let mut ix = encoded.last().copied().unwrap_or(0);
+
+        while len < MAX_SAMPLE_LEN {
+            let mut row = self.probabilities.i(ix)?.to_vec1::<f32>()?;
+            mask_end_token(&mut row, len, 0);
+            ix = sample_from_probs(&row, rng)?;
+            len += 1;
+            if ix == 0 {
+                break;
+            }
+            name.push_str(self.vocabulary.get_char(ix));
+        }
+
+        Ok(name)
+    }
+
+    /// Samples a name ending with `suffix`, resampling until one does.
+    ///
+    /// A forward bigram chain cannot be steered towards a given ending directly, so this
+    /// uses rejection sampling: whole names are drawn with [`BigramModel::sample_name`]
+    /// and discarded unless they end with the suffix. Rare suffixes may need many
+    /// attempts.
+    ///
+    /// # Arguments
+    /// * `suffix` - Characters the name must end with
+    /// * `max_attempts` - Maximum number of names to sample before giving up
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * `Some(name)` for the first name ending with `suffix`, or `None` if no attempt did
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "ava", "ellen", "anna"], &Device::Cpu)?;
+    /// let mut rng = rand::thread_rng();
+    ///
+    /// for _ in 0..10 {
+    ///     if let Some(name) = model.sample_with_suffix("na", 100, &mut rng)? {
+    ///         assert!(name.ends_with("na"), "{}", name);
+    ///     }
+    /// }
+    /// assert_eq!(model.sample_with_suffix("z", 100, &mut rng)?, None);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn sample_with_suffix<R: Rng + ?Sized>(
+        &self,
+        suffix: &str,
+        max_attempts: usize,
+        rng: &mut R,
+    ) -> Result<Option<String>, MakemoreError> {
+        for attempt in 0..max_attempts {
+            let name = self.sample_name(rng)?;
+            if name.ends_with(suffix) {
+                return Ok(Some(name));
+            }
+            debug!("Attempt {}: rejected name {} without suffix", attempt, name);
+        }
+
+        Ok(None)
+    }
+
+    /// Samples `n` names in parallel using rayon.
+    ///
+    /// Name `i` is drawn with its own `StdRng` seeded with `seed + i` (wrapping), so the
+    /// output is reproducible for a given seed and does not depend on how the work is
+    /// split across threads.
+    ///
+    /// # Arguments
+    /// * `n` - Number of names to sample
+    /// * `seed` - Base seed from which the per-name seeds are derived
+    ///
+    /// # Returns
+    /// * The generated names, in index order
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use rand::rngs::StdRng;
+    /// use rand::SeedableRng;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "olivia", "ava"], &Device::Cpu)?;
+    /// let parallel = model.sample_names_parallel(100, 42)?;
+    ///
+    /// let sequential = (0..100u64)
+    ///     .map(|i| model.sample_name(&mut StdRng::seed_from_u64(42 + i)))
+    ///     .collect::<Result<Vec<_>, _>>()?;
+    /// assert_eq!(parallel, sequential);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn sample_names_parallel(&self, n: usize, seed: u64) -> Result<Vec<String>, MakemoreError> {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+        use rayon::prelude::*;
+
+        (0..n)
+            .into_par_iter()
+            .map(|i| self.sample_name(&mut StdRng::seed_from_u64(seed.wrapping_add(i as u64))))
+            .collect()
+    }
+
+    /// Samples up to `n` distinct names, giving up after `max_attempts` samples.
+    ///
+    /// Duplicates are discarded, so on a model with a small reachable name space this
+    /// returns however many distinct names were found rather than looping forever.
+    ///
+    /// # Arguments
+    /// * `n` - Number of distinct names wanted
+    /// * `max_attempts` - Maximum number of names to sample in total
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * Distinct names in the order they were first generated
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// // Only "a" and "b" can ever be generated
+    /// let names = vec![NameItem::new("a"), NameItem::new("b")];
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    /// let mut rng = rand::thread_rng();
+    ///
+    /// let unique = model.sample_unique_names(5, 50, &mut rng)?;
+    /// assert!(unique.len() <= 2);
+    /// assert!(unique.iter().all(|name| name == "a" || name == "b"));
+    ///
+    /// assert!(model.sample_unique_names(5, 0, &mut rng)?.is_empty());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn sample_unique_names<R: Rng + ?Sized>(
+        &self,
+        n: usize,
+        max_attempts: usize,
+        rng: &mut R,
+    ) -> Result<Vec<String>, MakemoreError> {
+        let mut seen = HashSet::new();
+        let mut names = Vec::with_capacity(n);
+
+        for _ in 0..max_attempts {
+            if names.len() >= n {
+                break;
+            }
+            let name = self.sample_name(rng)?;
+            if seen.insert(name.clone()) {
+                names.push(name);
+            }
+        }
+
+        Ok(names)
+    }
+
+    /// Samples `n` names into a single padded tensor of vocabulary indices.
+    ///
+    /// Row `i` holds the token indices of the `i`-th name, without the leading '.' start
+    /// token. The remainder of the row is filled with 0, the index of '.', so the first 0
+    /// both ends the name and starts the padding. As with [`BigramModel::sample_name`], the
+    /// end token cannot be drawn on the first step, so no row starts with 0. Names that
+    /// would be longer than `max_len` are cut off, in which case the row contains no 0 at
+    /// all.
+    ///
+    /// # Arguments
+    /// * `n` - Number of names to sample
+    /// * `max_len` - Number of columns, the maximum number of tokens per name
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * A `u32` tensor of shape `[n, max_len]`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "olivia", "ava"], &Device::Cpu)?;
+    /// let batch = model.sample_batch_tensor(8, 10, &mut rand::thread_rng())?;
+    /// assert_eq!(batch.dims(), &[8, 10]);
+    ///
+    /// let vocab = model.get_vocabulary();
+    /// for row in batch.to_vec2::<u32>()? {
+    ///     let len = row.iter().position(|&ix| ix == 0).unwrap_or(row.len());
+    ///     assert!(row[len..].iter().all(|&ix| ix == 0));
+    ///
+    ///     let indices: Vec<usize> = row[..len].iter().map(|&ix| ix as usize).collect();
+    ///     let name = vocab.decode(&indices);
+    ///     assert_eq!(vocab.encode(&name)?, indices);
+    /// }
+    ///
+    /// // Even when most training names are empty, no sampled row is
+    /// let model = BigramModel::from_words(&["ava", "", "", ""], &Device::Cpu)?;
+    /// let batch = model.sample_batch_tensor(32, 5, &mut rand::thread_rng())?;
+    /// assert!(batch.to_vec2::<u32>()?.iter().all(|row| row[0] != 0));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn sample_batch_tensor<R: Rng + ?Sized>(
+        &self,
+        n: usize,
+        max_len: usize,
+        rng: &mut R,
+    ) -> Result<Tensor, MakemoreError> {
+        let probabilities = self.probabilities.to_vec2::<f32>()?;
+        let mut indices = vec![0u32; n * max_len];
+
+        // chunks_mut panics on a zero chunk size, and with max_len == 0 there are no rows
+        for row in indices.chunks_mut(max_len.max(1)) {
+            let mut ix = 0;
+            for (len, slot) in row.iter_mut().enumerate() {
+                let mut weights = probabilities[ix].clone();
+                mask_end_token(&mut weights, len, 0);
+                ix = sample_from_probs(&weights, rng)?;
+                if ix == 0 {
+                    break;
+                }
+                *slot = ix as u32;
+            }
+        }
+
+        Ok(Tensor::from_vec(
+            indices,
+            (n, max_len),
+            self.probabilities.device(),
+        )?)
+    }
+
+    /// Samples a name that is not in the `disallowed` set, resampling on collisions.
+    ///
+    /// Pass the training names as `disallowed` to generate only novel names.
+    ///
+    /// # Arguments
+    /// * `disallowed` - Names that must not be returned
+    /// * `max_attempts` - Maximum number of names to sample before giving up
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * `Some(name)` for the first allowed name, or `None` if every attempt collided
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let names = vec![NameItem::new("a"), NameItem::new("b"), NameItem::new("ab")];
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    /// let disallowed: HashSet<String> = ["a", "b"].iter().map(|s| s.to_string()).collect();
+    ///
+    /// let mut rng = rand::thread_rng();
+    /// for _ in 0..10 {
+    ///     if let Some(name) = model.sample_novel_name(&disallowed, 100, &mut rng)? {
+    ///         assert!(!disallowed.contains(&name));
+    ///     }
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn sample_novel_name<R: Rng + ?Sized>(
+        &self,
+        disallowed: &HashSet<String>,
+        max_attempts: usize,
+        rng: &mut R,
+    ) -> Result<Option<String>, MakemoreError> {
+        for attempt in 0..max_attempts {
+            let name = self.sample_name(rng)?;
+            if !disallowed.contains(&name) {
+                return Ok(Some(name));
+            }
+            debug!("Attempt {}: rejected disallowed name {}", attempt, name);
+        }
+
+        Ok(None)
+    }
+
+    /// Generates a name by always picking the most likely next character.
+    ///
+    /// Starting from the '.' token, the argmax of each conditional row is taken until the
+    /// end token is reached. Because the most likely transitions can form a cycle, the
+    /// output is capped at a maximum length.
+    ///
+    /// # Returns
+    /// * The deterministic greedy name without boundary tokens
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let names = vec![NameItem::new("emma"), NameItem::new("ava"), NameItem::new("anna")];
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    ///
+    /// let greedy = model.generate_greedy()?;
+    /// assert_eq!(greedy, model.generate_greedy()?);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn generate_greedy(&self) -> Result<String, MakemoreError> {
+        let best_next = self.probabilities.argmax(1)?.to_vec1::<u32>()?;
+        let mut name = String::new();
+        let mut ix = best_next[0] as usize;
+
+        for _ in 0..MAX_GREEDY_LEN {
+            if ix == 0 {
+                break;
+            }
+            name.push_str(self.vocabulary.get_char(ix));
+            ix = best_next[ix] as usize;
+        }
+
+        Ok(name)
+    }
+
+    /// Returns the most probable character to follow `prev`.
+    ///
+    /// Ties go to the character that comes first in the vocabulary. The result may be
+    /// "." when the context most often ends a name.
+    ///
+    /// # Arguments
+    /// * `prev` - The preceding character, or "." for the start of a name
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if `prev` is not in the vocabulary
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["quinn", "quentin", "raquel"], &Device::Cpu)?;
+    /// assert_eq!(model.most_likely_next("q")?, "u");
+    /// assert!(model.most_likely_next("z").is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn most_likely_next(&self, prev: &str) -> Result<String, MakemoreError> {
+        let i = self.vocabulary.get_index(prev)?;
+        let best = self.probabilities.i(i)?.argmax(0)?.to_scalar::<u32>()?;
+        Ok(self.vocabulary.get_char(best as usize).to_string())
+    }
+
+    /// Computes the average KL divergence `KL(self || other)` over the conditional rows.
+    ///
+    /// For every context character the divergence between this model's next-character
+    /// distribution and `other`'s is computed (in nats), and the results are averaged.
+    /// Contexts with no probability mass in this model are skipped.
+    ///
+    /// # Arguments
+    /// * `other` - Model to compare against
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidArgument`] if the models have different vocabularies
+    /// * [`MakemoreError::InvalidProbabilities`] if `other` assigns zero probability to a
+    ///   transition this model considers possible, which makes the divergence infinite
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let names = vec![NameItem::new("emma"), NameItem::new("ava"), NameItem::new("anna")];
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    /// assert!(model.kl_divergence(&model)?.abs() < 1e-6);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn kl_divergence(&self, other: &BigramModel) -> Result<f32, MakemoreError> {
+        if self.vocabulary.get_chars() != other.vocabulary.get_chars() {
+            return Err(MakemoreError::InvalidArgument(
+                "Cannot compare models with different vocabularies".to_string(),
+            ));
+        }
+
+        let p = self.probabilities.to_vec2::<f32>()?;
+        let q = other.probabilities.to_vec2::<f32>()?;
+
+        let mut total = 0.0;
+        let mut rows = 0;
+        for (i, (p_row, q_row)) in p.iter().zip(&q).enumerate() {
+            if p_row.iter().all(|&x| x <= 0.0) {
+                continue;
+            }
+
+            let mut kl = 0.0;
+            for (j, (&p_ij, &q_ij)) in p_row.iter().zip(q_row).enumerate() {
+                if p_ij <= 0.0 {
+                    continue;
+                }
+                if q_ij <= 0.0 {
+                    return Err(MakemoreError::InvalidProbabilities(format!(
+                        "Transition {}{} has zero probability in the other model",
+                        self.vocabulary.get_char(i),
+                        self.vocabulary.get_char(j)
+                    )));
+                }
+                kl += p_ij * (p_ij / q_ij).ln();
+            }
+            total += kl;
+            rows += 1;
+        }
+
+        Ok(if rows == 0 { 0.0 } else { total / rows as f32 })
+    }
+
+    /// Computes the log likelihood of `names`, summed over every transition.
+    ///
+    /// This is the quantity makemore reports as `log_likelihood`. All transitions are
+    /// gathered from the probability tensor in a single pass: the rows of the preceding
+    /// characters are selected with `index_select` and the following characters picked
+    /// out with `gather`, so the log and sum run on-device instead of extracting one
+    /// scalar per bigram. Name weights are ignored; every transition counts once.
+    ///
+    /// # Arguments
+    /// * `names` - Slice of name items to evaluate
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if a name contains a character outside the
+    ///   vocabulary
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let names = vec![NameItem::new("ab"), NameItem::new("ab"), NameItem::new("ac")];
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    ///
+    /// // Only the transitions out of 'a' are uncertain: 'b' 2 / 3 and 'c' 1 / 3 of the time
+    /// let expected = 2.0 * (2f32 / 3.0).ln() + (1f32 / 3.0).ln();
+    /// assert!((model.log_likelihood(&names)? - expected).abs() < 1e-5);
+    /// assert!((model.nll(&names)? + expected).abs() < 1e-5);
+    /// assert!((model.normalized_nll(&names)? + expected / 9.0).abs() < 1e-5);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn log_likelihood(&self, names: &[NameItem]) -> Result<f32, MakemoreError> {
+        Ok(self.transition_log_likelihood(names)?.0)
+    }
+
+    /// Computes the negative log likelihood of `names`, summed over every transition.
+    ///
+    /// The negation of [`BigramModel::log_likelihood`]; see
+    /// [`BigramModel::normalized_nll`] for the per-transition average. A dataset without
+    /// transitions sums to `0.0`, whereas `normalized_nll` has nothing to average over and
+    /// returns an error.
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if a name contains a character outside the
+    ///   vocabulary
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::MakemoreError;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "ava"], &Device::Cpu)?;
+    /// assert_eq!(model.nll(&[])?, 0.0);
+    /// assert!(matches!(model.normalized_nll(&[]), Err(MakemoreError::EmptyInput(_))));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn nll(&self, names: &[NameItem]) -> Result<f32, MakemoreError> {
+        Ok(-self.log_likelihood(names)?)
+    }
+
+    /// Computes the average negative log likelihood of every transition in `names`.
+    ///
+    /// This is [`BigramModel::nll`] divided by the number of transitions, the loss
+    /// makemore trains and reports.
+    ///
+    /// # Arguments
+    /// * `names` - Slice of name items to evaluate
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if a name contains a character outside the
+    ///   vocabulary
+    /// * [`MakemoreError::EmptyInput`] if `names` has no transitions
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// let names = vec![NameItem::new("emma"), NameItem::new("olivia"), NameItem::new("ava")];
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    ///
+    /// // The slow way: one scalar lookup per bigram
+    /// let mut log_likelihood = 0.0;
+    /// let mut n = 0;
+    /// for name in &names {
+    ///     let tokens: Vec<String> = format!(".{}.", name.name)
+    ///         .chars()
+    ///         .map(|c| c.to_string())
+    ///         .collect();
+    ///     for pair in tokens.windows(2) {
+    ///         log_likelihood += model.probability(&pair[0], &pair[1])?.ln();
+    ///         n += 1;
+    ///     }
+    /// }
+    ///
+    /// let slow_nll = -log_likelihood / n as f32;
+    /// assert!((model.normalized_nll(&names)? - slow_nll).abs() < 1e-5);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn normalized_nll(&self, names: &[NameItem]) -> Result<f32, MakemoreError> {
+        let (log_likelihood, n) = self.transition_log_likelihood(names)?;
+        if n == 0 {
+            return Err(MakemoreError::EmptyInput("Dataset"));
+        }
+        Ok(-log_likelihood / n as f32)
+    }
+
+    /// Compares predicted transition probabilities with how often the transitions occur in
+    /// `names`, for a reliability diagram.
+    ///
+    /// Every transition position in `names` yields one prediction per vocabulary entry:
+    /// the model's probability of that entry following the context, with an outcome of 1
+    /// if it is the character that actually follows and 0 otherwise. Predictions are
+    /// grouped into `bins` equal-width bins over `[0, 1]`. A calibrated model has an
+    /// empirical frequency close to the mean predicted probability in every bin. Name
+    /// weights are ignored.
+    ///
+    /// # Arguments
+    /// * `names` - Held-out names to evaluate
+    /// * `bins` - Number of probability bins
+    ///
+    /// # Returns
+    /// * `(mean_predicted, empirical_frequency, count)` for each non-empty bin, in order of
+    ///   increasing probability
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidArgument`] if `bins` is 0
+    /// * [`MakemoreError::NotInVocabulary`] if a name contains a character outside the
+    ///   vocabulary
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    ///
+    /// // Maximum likelihood estimates are exactly calibrated on their own training data
+    /// let names: Vec<NameItem> = ["emma", "olivia", "ava", "isabella", "sophia", "mia"]
+    ///     .into_iter()
+    ///     .map(NameItem::new)
+    ///     .collect();
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    ///
+    /// let curve = model.calibration_curve(&names, 10)?;
+    /// assert!(curve.len() > 1);
+    /// for (predicted, frequency, count) in curve {
+    ///     assert!(count > 0);
+    ///     assert!((predicted - frequency).abs() < 1e-4, "{} vs {}", predicted, frequency);
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn calibration_curve(
+        &self,
+        names: &[NameItem],
+        bins: usize,
+    ) -> Result<Vec<(f32, f32, usize)>, MakemoreError> {
+        if bins == 0 {
+            return Err(MakemoreError::InvalidArgument(
+                "Calibration needs at least one bin".to_string(),
+            ));
+        }
+
+        let probabilities = self.probabilities.to_vec2::<f32>()?;
+        let mut predicted = vec![0f64; bins];
+        let mut observed = vec![0f64; bins];
+        let mut counts = vec![0usize; bins];
+
+        for name in names {
+            let tokens = Self::tokenize(&self.vocabulary, &name.name)?;
+            for window in tokens.windows(2) {
+                for (j, &p) in probabilities[window[0]].iter().enumerate() {
+                    let bin = ((p * bins as f32) as usize).min(bins - 1);
+                    predicted[bin] += p as f64;
+                    counts[bin] += 1;
+                    if j == window[1] {
+                        observed[bin] += 1.0;
+                    }
+                }
+            }
+        }
+
+        Ok((0..bins)
+            .filter(|&bin| counts[bin] > 0)
+            .map(|bin| {
+                let n = counts[bin] as f64;
+                (
+                    (predicted[bin] / n) as f32,
+                    (observed[bin] / n) as f32,
+                    counts[bin],
+                )
+            })
+            .collect())
+    }
+
+    /// Returns the joint probability of `name` under the model.
+    ///
+    /// This is the product of the probabilities of every transition in the name,
+    /// including those out of the '.' start token and into the '.' end token. Long names
+    /// quickly underflow, so prefer [`BigramModel::name_log_probability`] when comparing
+    /// them. If the vocabulary reserves an [`UNK_TOKEN`](crate::vocabulary::UNK_TOKEN)
+    /// slot, unseen characters are scored as that token.
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if the name contains a character outside the
+    ///   vocabulary and there is no unknown token slot
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "ava", "anna"], &Device::Cpu)?;
+    ///
+    /// // "ava" only uses frequent transitions while "nmvea" mostly uses unseen ones
+    /// assert!(model.name_probability("ava")? > model.name_probability("nmvea")?);
+    /// assert!(model.name_log_probability("ava")? > model.name_log_probability("nmvea")?);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn name_probability(&self, name: &str) -> Result<f32, MakemoreError> {
+        Ok(self.name_log_probability(name)?.exp())
+    }
+
+    /// Returns the log of the joint probability of `name` under the model.
+    ///
+    /// The sum of the log probabilities of every transition, including the boundary
+    /// transitions. A name containing an unseen transition scores negative infinity.
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if the name contains a character outside the
+    ///   vocabulary
+    pub fn name_log_probability(&self, name: &str) -> Result<f32, MakemoreError> {
+        let tokens = Self::tokenize(&self.vocabulary, name)?;
+        let mut log_probability = 0.0;
+        for window in tokens.windows(2) {
+            let p = self
+                .probabilities
+                .i((window[0], window[1]))?
+                .to_scalar::<f32>()?;
+            log_probability += p.ln();
+        }
+        Ok(log_probability)
+    }
+
+    /// Returns the log probability of every name in `names`, scored in one pass.
+    ///
+    /// Gives the same results as calling [`BigramModel::name_log_probability`] per name,
+    /// but looks up all transitions with a single gather and sums them per name with
+    /// `index_add`, which is much faster when ranking a long candidate list.
+    ///
+    /// # Arguments
+    /// * `names` - Names to score
+    ///
+    /// # Returns
+    /// * The log probability of each name, in the order given
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if a name contains a character outside the
+    ///   vocabulary and there is no unknown token slot
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "ava", "anna"], &Device::Cpu)?;
+    /// let names = ["ava", "emma", "nmvea", ""];
+    ///
+    /// let batch = model.batch_name_log_probabilities(&names)?;
+    /// for (name, log_p) in names.iter().zip(batch) {
+    ///     let single = model.name_log_probability(name)?;
+    ///     assert!(log_p == single || (log_p - single).abs() < 1e-5, "{}", name);
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn batch_name_log_probabilities(&self, names: &[&str]) -> Result<Vec<f32>, MakemoreError> {
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut name_ids = Vec::new();
+        let mut prev = Vec::new();
+        let mut next = Vec::new();
+        for (id, name) in names.iter().enumerate() {
+            let tokens = Self::tokenize(&self.vocabulary, name)?;
+            for window in tokens.windows(2) {
+                name_ids.push(id as u32);
+                prev.push(window[0] as u32);
+                next.push(window[1] as u32);
+            }
+        }
+
+        let n = prev.len();
+        let device = self.probabilities.device();
+        let name_ids = Tensor::from_vec(name_ids, n, device)?;
+        let prev = Tensor::from_vec(prev, n, device)?;
+        let next = Tensor::from_vec(next, (n, 1), device)?;
+
+        let log_probs = self
+            .probabilities
+            .index_select(&prev, 0)?
+            .gather(&next, 1)?
+            .squeeze(1)?
+            .log()?;
+        let totals = Tensor::zeros(names.len(), DType::F32, device)?
+            .index_add(&name_ids, &log_probs, 0)?
+            .to_vec1::<f32>()?;
+        Ok(totals)
+    }
+
+    /// Returns the pointwise mutual information, in nats, of every observed bigram.
+    ///
+    /// `PMI(a, b) = ln(p(a, b) / (p(a) * p(b)))`, where `p(a, b)` is the share of all
+    /// transitions that are `a -> b`, `p(a)` the share starting with `a` and `p(b)` the
+    /// share ending with `b`. Positive values mark pairs that occur together more often
+    /// than their individual frequencies predict. Pairs that never occur would have a PMI
+    /// of negative infinity and are left out of the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let words = ["quinn", "quentin", "raquel", "anna", "emma", "ava"];
+    /// let model = BigramModel::from_words(&words, &Device::Cpu)?;
+    /// let pmi = model.pmi()?;
+    ///
+    /// // 'q' is always followed by 'u', and 'u' only ever follows 'q'
+    /// let qu = pmi[&("q".to_string(), "u".to_string())];
+    /// assert!(qu > 2.0);
+    /// assert!(qu > pmi[&("a".to_string(), ".".to_string())]);
+    /// assert!(!pmi.contains_key(&("q".to_string(), "q".to_string())));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn pmi(&self) -> Result<HashMap<(String, String), f32>, MakemoreError> {
+        let counts = self.count_tensor.to_vec2::<f32>()?;
+        let chars = self.vocabulary.get_chars();
+        let total: f32 = counts.iter().flatten().sum();
+        let first: Vec<f32> = counts.iter().map(|row| row.iter().sum()).collect();
+        let mut second = vec![0f32; counts.len()];
+        for row in &counts {
+            for (j, &count) in row.iter().enumerate() {
+                second[j] += count;
+            }
+        }
+
+        let mut pmi = HashMap::new();
+        for (i, row) in counts.iter().enumerate() {
+            for (j, &count) in row.iter().enumerate() {
+                if count > 0.0 {
+                    let value = (count * total / (first[i] * second[j])).ln();
+                    pmi.insert((chars[i].clone(), chars[j].clone()), value);
+                }
+            }
+        }
+
+        Ok(pmi)
+    }
+
+    /// Returns the Shannon entropy, in bits, of the next-character distribution for each
+    /// preceding character.
+    ///
+    /// Low entropy means the context is highly predictive of what follows, while a context
+    /// followed uniformly by every character reaches the maximum of `log2(vocab_size)`.
+    /// Characters that never occur as a context report 0.
+    ///
+    /// # Returns
+    /// * `(character, entropy)` pairs in vocabulary order
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// // 'a' is followed by '.', 'a' and 'b' once each; 'b' always ends the name
+    /// let model = BigramModel::from_words(&["a", "aab"], &Device::Cpu)?;
+    /// let entropy: std::collections::HashMap<_, _> =
+    ///     model.conditional_entropy()?.into_iter().collect();
+    ///
+    /// assert_eq!(entropy["b"], 0.0);
+    /// assert!((entropy["a"] - 3f32.log2()).abs() < 1e-5);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn conditional_entropy(&self) -> Result<Vec<(String, f32)>, MakemoreError> {
+        let probabilities = self.probabilities.to_vec2::<f32>()?;
+        Ok(self
+            .vocabulary
+            .get_chars()
+            .iter()
+            .zip(&probabilities)
+            .map(|(c, row)| {
+                let entropy: f32 = row
+                    .iter()
+                    .filter(|&&p| p > 0.0)
+                    .map(|&p| -p * p.log2())
+                    .sum();
+                (c.clone(), entropy)
+            })
+            .collect())
+    }
+
+    /// Returns the pairwise cosine similarity between the next-character distributions of
+    /// every context.
+    ///
+    /// Entry `[i][j]` compares the probability rows of `get_chars()[i]` and
+    /// `get_chars()[j]`: 1 means both are followed by the same characters in the same
+    /// proportions, 0 that they share no successors. Characters that behave alike, such
+    /// as vowels, score high, which makes the matrix a starting point for clustering.
+    /// Contexts that never occur have an all-zero row and a similarity of 0 to everything,
+    /// themselves included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// // 'a' and 'c' are both always followed by 'b'
+    /// let model = BigramModel::from_words(&["ab", "cb", "bb"], &Device::Cpu)?;
+    /// let idx = model.get_vocabulary().get_char_to_idx();
+    /// let similarity = model.context_similarity()?;
+    ///
+    /// assert!((similarity[idx["a"]][idx["c"]] - 1.0).abs() < 1e-6);
+    /// assert!(similarity[idx["a"]][idx["b"]] < 1.0);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn context_similarity(&self) -> Result<Vec<Vec<f32>>, MakemoreError> {
+        let norms = self
+            .probabilities
+            .sqr()?
+            .sum_keepdim(1)?
+            .sqrt()?
+            .maximum(f32::MIN_POSITIVE)?;
+        let unit_rows = self.probabilities.broadcast_div(&norms)?;
+        Ok(unit_rows
+            .matmul(&unit_rows.t()?.contiguous()?)?
+            .to_vec2::<f32>()?)
+    }
+
+    /// Computes the stationary distribution of the Markov chain defined by the transition
+    /// probabilities, using power iteration.
+    ///
+    /// The '.' token both ends a name and starts the next one, so rather than being
+    /// absorbing it links names into an endless stream. The stationary distribution is the
+    /// long-run share of each token in that stream, and the '.' entry is one over the
+    /// expected name length plus one. Contexts without any probability mass would leak
+    /// mass, so the distribution is renormalized after every step.
+    ///
+    /// Iteration starts from the uniform distribution and stops early once the L1 change
+    /// between steps drops below `1e-7`.
+    ///
+    /// # Arguments
+    /// * `iterations` - Maximum number of power iteration steps
+    ///
+    /// # Returns
+    /// * `(character, probability)` pairs in vocabulary order
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// // Every token is entered with total probability 1, so the chain is doubly
+    /// // stochastic and its stationary distribution is uniform
+    /// let model = BigramModel::from_words(&["ab", "ba"], &Device::Cpu)?;
+    /// for (_, p) in model.stationary_distribution(1000)? {
+    ///     assert!((p - 1.0 / 3.0).abs() < 1e-4);
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn stationary_distribution(
+        &self,
+        iterations: usize,
+    ) -> Result<Vec<(String, f32)>, MakemoreError> {
+        let size = self.vocabulary.get_size();
+        let mut pi = Tensor::full(1.0 / size as f32, (1, size), self.probabilities.device())?;
+
+        for step in 0..iterations {
+            let next = pi.matmul(&self.probabilities)?;
+            let next = next.broadcast_div(&next.sum_keepdim(1)?)?;
+            let change = (&next - &pi)?.abs()?.sum_all()?.to_scalar::<f32>()?;
+            pi = next;
+            if change < 1e-7 {
+                debug!("Stationary distribution converged after {} steps", step + 1);
+                break;
+            }
+        }
+
+        Ok(self
+            .vocabulary
+            .get_chars()
+            .iter()
+            .cloned()
+            .zip(pi.squeeze(0)?.to_vec1::<f32>()?)
+            .collect())
+    }
+
+    /// Samples `n` names and compares their character and length distributions with
+    /// those of the training names.
+    ///
+    /// The training distributions come from what the model kept of its training names:
+    /// character frequencies are read from the count tensor, so they reflect any pruning,
+    /// capping or smoothing applied to the counts, and lengths from the histogram
+    /// recorded at construction and by [`BigramModel::add_name`]. Name weights apply to
+    /// both. Names are generated with [`BigramModel::generate_iter`], which never produces
+    /// an empty name, so empty training names only show up as a length the generated names
+    /// never have.
     ///
     /// # Arguments
-    /// * `names` - Slice of name items used to build the vocabulary
-    /// * `device` - Device to store tensors on (CPU/GPU)
-    pub fn new(names: &[NameItem], device: &Device) -> Result<Self> {
-        let vocabulary = Vocabulary::new(names);
-        let vocab_size = vocabulary.get_size();
+    /// * `n` - Number of names to generate
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Errors
+    /// * [`MakemoreError::EmptyInput`] if the model was built without any names
+    /// * [`MakemoreError::InvalidArgument`] if `n` is 0
+    /// * [`MakemoreError::InvalidProbabilities`] if a probability row reached while sampling
+    ///   has no mass or holds a negative or NaN value
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    /// use rand::rngs::StdRng;
+    /// use rand::SeedableRng;
+    ///
+    /// let names: Vec<NameItem> = ["emma", "olivia", "ava", "isabella", "sophia", "mia"]
+    ///     .into_iter()
+    ///     .map(NameItem::new)
+    ///     .collect();
+    /// let model = BigramModel::new(&names, &Device::Cpu)?;
+    /// let mut rng = StdRng::seed_from_u64(42);
+    /// let stats = model.generation_stats(2000, &mut rng)?;
+    ///
+    /// // A bigram model reproduces the training character frequencies closely
+    /// assert!(stats.char_js < 0.01, "{}", stats.char_js);
+    /// assert!(stats.length_js <= std::f32::consts::LN_2);
+    /// assert_eq!(stats.training_lengths[&3], 2.0 / 6.0);
+    ///
+    /// assert!(model.generation_stats(0, &mut rng).is_err());
+    /// let empty = BigramModel::new(&[], &Device::Cpu)?;
+    /// assert!(empty.generation_stats(2000, &mut rng).is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn generation_stats<R: Rng + ?Sized>(
+        &self,
+        n: usize,
+        rng: &mut R,
+    ) -> Result<GenerationStats, MakemoreError> {
+        if self.lengths.is_empty() {
+            return Err(MakemoreError::EmptyInput("Training set"));
+        }
+        if n == 0 {
+            return Err(MakemoreError::InvalidArgument(
+                "Cannot compute generation statistics without generating any names".to_string(),
+            ));
+        }
+
+        let size = self.vocabulary.get_size();
+        let char_to_idx = self.vocabulary.get_char_to_idx();
+
+        // Every character of a training name is the second token of exactly one bigram
+        let training_counts = self.count_tensor.sum(0)?.to_vec1::<f32>()?;
+        let training_lengths = self.lengths.clone();
+
+        let mut generated_counts = vec![0f32; size];
+        let mut generated_lengths = BTreeMap::new();
+        for _ in 0..n {
+            let mut len = 0;
+            for c in self.generate_iter(rng)? {
+                generated_counts[char_to_idx[&c?]] += 1.0;
+                len += 1;
+            }
+            *generated_lengths.entry(len).or_insert(0.0) += 1.0;
+        }
+
+        // Character distributions leave out the '.' token at index 0
+        let generated_chars = Self::normalize(&generated_counts[1..]);
+        let training_chars = Self::normalize(&training_counts[1..]);
+        let lengths: Vec<usize> = generated_lengths
+            .keys()
+            .chain(training_lengths.keys())
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let aligned = |counts: &BTreeMap<usize, f32>| -> Vec<f32> {
+            lengths
+                .iter()
+                .map(|len| counts.get(len).copied().unwrap_or(0.0))
+                .collect()
+        };
+        let generated_length_probs = Self::normalize(&aligned(&generated_lengths));
+        let training_length_probs = Self::normalize(&aligned(&training_lengths));
+
+        let label = |probs: Vec<f32>| -> Vec<(String, f32)> {
+            self.vocabulary.get_chars()[1..]
+                .iter()
+                .cloned()
+                .zip(probs)
+                .collect()
+        };
+        let share = |counts: BTreeMap<usize, f32>| -> BTreeMap<usize, f32> {
+            let total: f32 = counts.values().sum();
+            counts
+                .into_iter()
+                .map(|(len, count)| (len, count / total))
+                .collect()
+        };
+
+        Ok(GenerationStats {
+            char_kl: Self::kl(&generated_chars, &training_chars),
+            char_js: Self::js(&generated_chars, &training_chars),
+            length_kl: Self::kl(&generated_length_probs, &training_length_probs),
+            length_js: Self::js(&generated_length_probs, &training_length_probs),
+            generated_chars: label(generated_chars),
+            training_chars: label(training_chars),
+            generated_lengths: share(generated_lengths),
+            training_lengths: share(training_lengths),
+        })
+    }
+
+    /// Renders the transition graph in Graphviz DOT format.
+    ///
+    /// Nodes are characters (including the '.' boundary token) and each edge is a
+    /// transition whose probability exceeds `min_prob`, labeled with that probability.
+    /// Pipe the output to `dot -Tpng -o bigrams.png` to draw it.
+    ///
+    /// # Arguments
+    /// * `min_prob` - Transitions with probability at or below this are omitted
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let words = ["ab", "ab", "ab", "ac"];
+    /// let model = BigramModel::from_words(&words, &Device::Cpu)?;
+    /// let dot = model.to_dot(0.5)?;
+    ///
+    /// assert!(dot.starts_with("digraph bigrams {"));
+    /// assert!(dot.contains("\"a\" -> \"b\" [label=\"0.75\"];"));
+    /// assert!(!dot.contains("\"a\" -> \"c\""));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn to_dot(&self, min_prob: f32) -> Result<String, MakemoreError> {
+        let probabilities = self.probabilities.to_vec2::<f32>()?;
+        let chars = self.vocabulary.get_chars();
+
+        let mut dot = String::from("digraph bigrams {\n");
+        for c in chars {
+            dot.push_str(&format!("    {:?};\n", c));
+        }
+        for (i, row) in probabilities.iter().enumerate() {
+            for (j, &p) in row.iter().enumerate() {
+                if p > min_prob {
+                    dot.push_str(&format!(
+                        "    {:?} -> {:?} [label=\"{:.2}\"];\n",
+                        chars[i], chars[j], p
+                    ));
+                }
+            }
+        }
+        dot.push_str("}\n");
+        Ok(dot)
+    }
+
+    /// Renders the count tensor as a grid of shading characters for terminal output.
+    ///
+    /// Rows are the first character of a bigram and columns the second, both labeled
+    /// with the vocabulary. Zero counts are blank and non-zero counts use `░▒▓█` in
+    /// proportion to the largest count, so every observed transition is visible.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["ab", "ab", "ba"], &Device::Cpu)?;
+    /// let heatmap = model.ascii_heatmap()?;
+    /// let lines: Vec<&str> = heatmap.lines().collect();
+    ///
+    /// assert_eq!(lines[0], "  . a b");
+    /// assert_eq!(lines[1], ".   █ ▒");
+    /// assert!(lines[2].starts_with("a "));
+    /// assert!(lines[3].starts_with("b "));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn ascii_heatmap(&self) -> Result<String, MakemoreError> {
+        let counts = self.count_tensor.to_vec2::<f32>()?;
+        let chars = self.vocabulary.get_chars();
+        let width = chars.iter().map(|c| c.chars().count()).max().unwrap_or(1);
+        let max = counts.iter().flatten().fold(0f32, |max, &c| max.max(c));
+
+        let mut heatmap = format!("{:width$}", "");
+        for c in chars {
+            heatmap.push_str(&format!(" {:>width$}", c));
+        }
+        heatmap.push('\n');
+
+        for (c, row) in chars.iter().zip(&counts) {
+            heatmap.push_str(&format!("{:>width$}", c));
+            for &count in row {
+                let level = if count > 0.0 {
+                    ((count / max * 4.0).ceil() as usize).clamp(1, 4)
+                } else {
+                    0
+                };
+                let cell = ASCII_SHADES[level].to_string().repeat(width);
+                heatmap.push(' ');
+                heatmap.push_str(&cell);
+            }
+            heatmap.push('\n');
+        }
+
+        Ok(heatmap)
+    }
+
+    /// Prints [`BigramModel::ascii_heatmap`] to stdout.
+    pub fn print_ascii_heatmap(&self) -> Result<(), MakemoreError> {
+        print!("{}", self.ascii_heatmap()?);
+        Ok(())
+    }
+
+    /// Writes the count and probability tensors to a safetensors file.
+    ///
+    /// The tensors are stored as "counts" and "probs", and the vocabulary as a JSON array
+    /// under the "vocabulary" metadata key, so row and column `i` of both tensors belong
+    /// to `vocabulary[i]`. In Python the file loads with
+    /// `safetensors.numpy.load_file(path)`, and the metadata with `safe_open(path, "np")`.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the file to write
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    ///
+    /// let model = BigramModel::from_words(&["emma", "ava"], &Device::Cpu)?;
+    /// let path = std::env::temp_dir().join("bigrams.safetensors");
+    /// let path = path.to_str().unwrap();
+    /// model.export_safetensors(path)?;
+    ///
+    /// let tensors = candle_core::safetensors::load(path, &Device::Cpu)?;
+    /// assert_eq!(tensors["counts"].dims(), model.get_tensor().dims());
+    /// assert_eq!(tensors["probs"].dims(), model.get_probabilities().dims());
+    /// assert_eq!(
+    ///     tensors["probs"].to_vec2::<f32>()?,
+    ///     model.probabilities_matrix()?
+    /// );
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn export_safetensors(&self, path: &str) -> Result<(), MakemoreError> {
+        let metadata = HashMap::from([(
+            "vocabulary".to_string(),
+            serde_json::to_string(self.vocabulary.get_chars())
+                .map_err(|e| MakemoreError::Serialization(e.to_string()))?,
+        )]);
+        safetensors::serialize_to_file(
+            [
+                ("counts", &self.count_tensor),
+                ("probs", &self.probabilities),
+            ],
+            &Some(metadata),
+            std::path::Path::new(path),
+        )
+        .map_err(|e| MakemoreError::Serialization(e.to_string()))?;
+        debug!("Exported counts and probabilities to {}", path);
+        Ok(())
+    }
 
-        // Initialize and compute count tensor
-        let mut count_tensor = Tensor::zeros((vocab_size, vocab_size), DType::F32, device)?;
+    // Private helper methods below
+
+    /// Looks up the vocabulary indices of a character pair.
+    fn pair_indices(&self, a: &str, b: &str) -> Result<(usize, usize), MakemoreError> {
+        Ok((self.vocabulary.get_index(a)?, self.vocabulary.get_index(b)?))
+    }
 
+    /// Returns the log likelihood of `names` summed over every transition, along with the
+    /// number of transitions.
+    fn transition_log_likelihood(&self, names: &[NameItem]) -> Result<(f32, usize), MakemoreError> {
+        let mut prev = Vec::new();
+        let mut next = Vec::new();
         for name in names {
-            let tokens =
-                Self::tokenize(&name.name.chars().map(|c| c.to_string()).collect::<Vec<_>>());
+            let tokens = Self::tokenize(&self.vocabulary, &name.name)?;
             for window in tokens.windows(2) {
-                let char_to_idx = vocabulary.get_char_to_idx();
-                let i = char_to_idx[&window[0]];
-                let j = char_to_idx[&window[1]];
-                let current = count_tensor.i((i, j))?.to_scalar::<f32>()?;
-                let new_value = Tensor::new(&[[current + 1.0]], device)?;
-                count_tensor = count_tensor.slice_assign(&[i..=i, j..=j], &new_value)?;
+                prev.push(window[0] as u32);
+                next.push(window[1] as u32);
             }
         }
+        if prev.is_empty() {
+            return Ok((0.0, 0));
+        }
 
-        // Compute probabilities
-        let probs = count_tensor.to_dtype(DType::F32)?;
-        let row_sums = probs.sum_keepdim(1)?;
-        debug!(
-            "Row sums shape: {:?}, values: {:?}",
-            row_sums.dims(),
-            row_sums.to_vec2::<f32>()?
-        );
+        let n = prev.len();
+        let device = self.probabilities.device();
+        let prev = Tensor::from_vec(prev, n, device)?;
+        let next = Tensor::from_vec(next, (n, 1), device)?;
 
-        let probabilities = probs.broadcast_div(&row_sums)?;
-        debug!("Probability tensor shape: {:?}", probabilities.dims());
-        debug!(
-            "First row probabilities sum: {}",
-            probabilities
-                .i((0, 0..))?
-                .to_vec1::<f32>()?
-                .iter()
-                .sum::<f32>()
-        );
+        let log_likelihood = self
+            .probabilities
+            .index_select(&prev, 0)?
+            .gather(&next, 1)?
+            .log()?
+            .sum_all()?
+            .to_scalar::<f32>()?;
+        Ok((log_likelihood, n))
+    }
+
+    /// Walks the bigram chain of the `[vocab_size, vocab_size]` `probabilities` from the
+    /// start token, suppressing the end token until `min_len` characters (and at least
+    /// one) are generated and stopping after `max_len`. Returns the name and the
+    /// unsuppressed probability of every transition taken.
+    pub(crate) fn sample_walk<R: Rng + ?Sized>(
+        probabilities: &Tensor,
+        vocabulary: &Vocabulary,
+        min_len: usize,
+        max_len: usize,
+        rng: &mut R,
+    ) -> Result<(String, Vec<f32>), MakemoreError> {
+        let mut name = String::new();
+        let mut trace = Vec::new();
+        let mut len = 0;
+        let mut ix = 0;
+
+        while len < max_len {
+            let row = probabilities.i(ix)?.to_vec1::<f32>()?;
+            let mut weights = row.clone();
+            mask_end_token(&mut weights, len, min_len);
+
+            ix = sample_from_probs(&weights, rng)?;
+            trace.push(row[ix]);
+            if ix == 0 {
+                break;
+            }
+            name.push_str(vocabulary.get_char(ix));
+            len += 1;
+        }
+
+        Ok((name, trace))
+    }
+
+    /// Counts the bigrams of `names` with [`BigramModel::count_pairs_parallel`].
+    #[cfg(feature = "rayon")]
+    fn pair_counts(
+        vocabulary: &Vocabulary,
+        names: &[NameItem],
+    ) -> Result<HashMap<(usize, usize), f32>, MakemoreError> {
+        Self::count_pairs_parallel(vocabulary, names)
+    }
+
+    /// Counts the bigrams of `names` with [`BigramModel::count_pairs`].
+    #[cfg(not(feature = "rayon"))]
+    fn pair_counts(
+        vocabulary: &Vocabulary,
+        names: &[NameItem],
+    ) -> Result<HashMap<(usize, usize), f32>, MakemoreError> {
+        Self::count_pairs(vocabulary, names)
+    }
+
+    /// Builds a model from an already accumulated count tensor, computing the
+    /// normalized probabilities and the non-zero counts map. `lengths` is the length
+    /// histogram of the names the counts were accumulated from.
+    fn from_counts(
+        vocabulary: Vocabulary,
+        count_tensor: Tensor,
+        lengths: BTreeMap<usize, f32>,
+    ) -> Result<Self, MakemoreError> {
+        let vocab_size = vocabulary.get_size();
+        let empty_rows = EmptyRowPolicy::default();
+        let probabilities = Self::compute_probabilities(&count_tensor, empty_rows)?;
 
         // Compute hashmap counts
         let counts = (0..vocab_size)
@@ -91,141 +3033,133 @@ impl BigramModel {
             counts,
             count_tensor,
             probabilities,
+            stale: false,
+            empty_rows,
+            lengths,
         })
     }
 
-    pub fn get_vocabulary(&self) -> &Vocabulary {
-        &self.vocabulary
+    /// Tallies the weighted number of `names` of each length, in tokens.
+    fn name_lengths(
+        vocabulary: &Vocabulary,
+        names: &[NameItem],
+    ) -> Result<BTreeMap<usize, f32>, MakemoreError> {
+        let mut lengths = BTreeMap::new();
+        for name in names {
+            let len = Self::tokenize(vocabulary, &name.name)?.len() - 2;
+            *lengths.entry(len).or_insert(0.0) += name.weight;
+        }
+        Ok(lengths)
     }
 
-    pub fn get_counts(&self) -> &HashMap<(String, String), i32> {
-        &self.counts
-    }
+    /// Normalizes each row of a count tensor into a probability distribution, filling
+    /// all-zero rows according to `empty_rows` instead of dividing by zero.
+    fn compute_probabilities(
+        count_tensor: &Tensor,
+        empty_rows: EmptyRowPolicy,
+    ) -> Result<Tensor, MakemoreError> {
+        let probs = count_tensor.to_dtype(DType::F32)?;
+        let row_sums = probs.sum_keepdim(1)?;
+        debug!(
+            "Row sums shape: {:?}, values: {:?}",
+            row_sums.dims(),
+            row_sums.to_vec2::<f32>()?
+        );
 
-    pub fn get_chars(&self) -> &Vec<String> {
-        self.vocabulary.get_chars()
+        // Divide empty rows by 1 so they stay at zero rather than becoming NaN
+        let empty = row_sums.eq(0f32)?;
+        let safe_sums = empty.where_cond(&row_sums.ones_like()?, &row_sums)?;
+        let mut probabilities = probs.broadcast_div(&safe_sums)?;
+        if empty_rows == EmptyRowPolicy::Uniform {
+            let uniform = (probabilities.ones_like()? / probabilities.dim(1)? as f64)?;
+            probabilities = empty
+                .broadcast_as(probabilities.shape())?
+                .where_cond(&uniform, &probabilities)?;
+        }
+        debug!("Probability tensor shape: {:?}", probabilities.dims());
+        debug!(
+            "First row probabilities sum: {}",
+            probabilities
+                .i((0, 0..))?
+                .to_vec1::<f32>()?
+                .iter()
+                .sum::<f32>()
+        );
+        Ok(probabilities)
     }
 
-    pub fn get_tensor(&self) -> &Tensor {
-        &self.count_tensor
+    /// Builds a dense `[vocab_size, vocab_size]` count tensor from sparse pair counts.
+    fn pair_counts_to_tensor(
+        pair_counts: &HashMap<(usize, usize), f32>,
+        vocab_size: usize,
+        device: &Device,
+    ) -> Result<Tensor, MakemoreError> {
+        let mut xs = Vec::with_capacity(pair_counts.len());
+        let mut ys = Vec::with_capacity(pair_counts.len());
+        let mut weights = Vec::with_capacity(pair_counts.len());
+        for (&(i, j), &count) in pair_counts {
+            xs.push(i as i64);
+            ys.push(j as i64);
+            weights.push(count);
+        }
+        counts_from_weighted_pairs(&xs, &ys, &weights, vocab_size, device)
     }
 
-    pub fn get_probabilities(&self) -> &Tensor {
-        &self.probabilities
+    /// Scales non-negative counts to sum to 1, leaving all-zero counts unchanged.
+    fn normalize(counts: &[f32]) -> Vec<f32> {
+        let total: f32 = counts.iter().sum();
+        if total <= 0.0 {
+            return counts.to_vec();
+        }
+        counts.iter().map(|count| count / total).collect()
     }
 
-    pub fn get_probabilities_map(&self) -> Option<HashMap<(String, String), f32>> {
-        let probabilities = &self.probabilities;
-        let chars = self.vocabulary.get_chars();
-        probabilities.to_dtype(DType::F32).ok().and_then(|p| {
-            p.to_dtype(DType::F32)
-                .ok()?
-                .to_vec2::<f32>()
-                .ok()
-                .map(|data| {
-                    data.iter()
-                        .enumerate()
-                        .flat_map(|(i, row)| {
-                            row.iter()
-                                .enumerate()
-                                .map(move |(j, &v)| ((chars[i].clone(), chars[j].clone()), v))
-                        })
-                        .collect()
-                })
-        })
+    /// KL divergence `KL(p || q)` in nats between two aligned distributions.
+    fn kl(p: &[f32], q: &[f32]) -> f32 {
+        p.iter()
+            .zip(q)
+            .filter(|(&p, _)| p > 0.0)
+            .map(|(&p, &q)| p * (p / q).ln())
+            .sum()
     }
 
-    /// Samples indices from a probability distribution using the multinomial distribution.
-    ///
-    /// # Arguments
-    /// * `probs` - Tensor containing probabilities
-    /// * `num_samples` - Number of samples to draw
-    /// * `replacement` - Whether to sample with replacement
-    ///
-    /// # Returns
-    /// * Tensor containing sampled indices
-    pub fn multinomial(
-        &self,
-        probs: &Tensor,
-        num_samples: i64,
-        replacement: bool,
-    ) -> Result<Tensor> {
-        let device = probs.device();
-        let mut p = if probs.dims().len() > 1 {
-            debug!(
-                "Flattening probabilities tensor of shape {:?}",
-                probs.dims()
-            );
-            let flat = probs.flatten_all()?.to_vec1::<f32>()?;
-            // Normalize the flattened probabilities
-            let sum: f32 = flat.iter().sum();
-            flat.iter().map(|&x| x / sum).collect::<Vec<_>>()
-        } else {
-            let p = probs.to_vec1::<f32>()?;
-            let sum: f32 = p.iter().sum();
-            p.iter().map(|&x| x / sum).collect::<Vec<_>>()
-        };
-
-        debug!(
-            "Initial probability distribution sum: {}",
-            p.iter().sum::<f32>()
-        );
-
-        let mut samples = Vec::with_capacity(num_samples as usize);
-        let mut rng = rand::thread_rng();
-
-        for sample_idx in 0..num_samples {
-            // Recompute cumulative probabilities each time
-            let mut cumulative = vec![0.0; p.len()];
-            let mut sum = 0.0;
-            for (i, &prob) in p.iter().enumerate() {
-                sum += prob;
-                cumulative[i] = sum;
-            }
-            debug!("Sample {}: Cumulative sum: {}", sample_idx, sum);
-
-            let r: f32 = rng.gen::<f32>();
-            debug!("Sample {}: Random value: {}", sample_idx, r);
-
-            let selected_idx =
-                match cumulative.binary_search_by(|&cum| cum.partial_cmp(&r).unwrap()) {
-                    Ok(idx) => idx,
-                    Err(idx) => idx,
-                };
-
-            debug!(
-                "Sample {}: Selected index: {}, Probability: {}",
-                sample_idx, selected_idx, p[selected_idx]
-            );
-            samples.push(selected_idx as i64);
+    /// Jensen-Shannon divergence in nats between two aligned distributions.
+    fn js(p: &[f32], q: &[f32]) -> f32 {
+        let m: Vec<f32> = p.iter().zip(q).map(|(p, q)| 0.5 * (p + q)).collect();
+        0.5 * Self::kl(p, &m) + 0.5 * Self::kl(q, &m)
+    }
 
-            if !replacement {
-                // Zero out the selected probability and renormalize
-                p[selected_idx] = 0.0;
-                let new_sum: f32 = p.iter().sum();
-                if new_sum > 0.0 {
-                    for prob in p.iter_mut() {
-                        *prob /= new_sum;
-                    }
-                    debug!(
-                        "Sample {}: Renormalized distribution sum: {}",
-                        sample_idx,
-                        p.iter().sum::<f32>()
-                    );
-                }
+    /// Moves a count tensor into a larger vocabulary, placing entry `(i, j)` at
+    /// `(remap[i], remap[j])` and leaving the remaining cells at zero.
+    fn remap_counts(
+        count_tensor: &Tensor,
+        remap: &[usize],
+        size: usize,
+    ) -> Result<Tensor, MakemoreError> {
+        let counts = count_tensor.to_vec2::<f32>()?;
+        let mut remapped = vec![0f32; size * size];
+        for (i, row) in counts.iter().enumerate() {
+            for (j, &count) in row.iter().enumerate() {
+                remapped[remap[i] * size + remap[j]] = count;
             }
         }
-
-        debug!("Final samples: {:?}", samples);
-        Tensor::new(samples.as_slice(), device).map_err(|e| e.into())
+        Ok(Tensor::from_vec(
+            remapped,
+            (size, size),
+            count_tensor.device(),
+        )?)
     }
 
-    // Private helper methods below
-
-    fn tokenize(chars: &[String]) -> Vec<String> {
-        std::iter::once(".".to_string())
-            .chain(chars.iter().cloned())
-            .chain(std::iter::once(".".to_string()))
-            .collect()
+    /// Encodes a name with the vocabulary's tokenizer and wraps it in '.' boundary tokens
+    /// (index 0). Unseen characters map to the unknown token if the vocabulary has one.
+    fn tokenize(vocabulary: &Vocabulary, name: &str) -> Result<Vec<usize>, MakemoreError> {
+        let encoded = match vocabulary.get_unk_index() {
+            Some(_) => vocabulary.encode_with_unk(name)?,
+            None => vocabulary.encode(name)?,
+        };
+        Ok(std::iter::once(0)
+            .chain(encoded)
+            .chain(std::iter::once(0))
+            .collect())
     }
 }
@@ -4,19 +4,66 @@
 //! character-level language models and other neural network architectures that work
 //! with sequences of characters.
 
-use candle_core::{Device, Result, Tensor};
+use crate::vocabulary::Vocabulary;
+use crate::MakemoreError;
+use candle_core::{Device, Tensor};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use tracing::{debug, info};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Read};
+use tracing::{debug, info, warn};
 
 /// Represents a single name item in the dataset.
 ///
 /// This struct is used as the basic unit of data, containing a single name that can
 /// be processed for neural network training.
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug)]
 pub struct NameItem {
     /// The actual name string
     pub name: String,
+    /// How much each occurrence of this name counts towards the bigram statistics.
+    /// Defaults to 1.0; datasets with frequency columns (e.g. census counts) use larger values.
+    pub weight: f32,
+}
+
+impl NameItem {
+    /// Creates a name item with the default weight of 1.0
+    ///
+    /// # Arguments
+    /// * `name` - The name string
+    pub fn new(name: impl Into<String>) -> Self {
+        Self::with_weight(name, 1.0)
+    }
+
+    /// Creates a name item with an explicit weight
+    ///
+    /// # Arguments
+    /// * `name` - The name string
+    /// * `weight` - Multiplier applied to every bigram occurrence in this name
+    pub fn with_weight(name: impl Into<String>, weight: f32) -> Self {
+        Self {
+            name: name.into(),
+            weight,
+        }
+    }
+}
+
+impl PartialEq for NameItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.weight.to_bits() == other.weight.to_bits()
+    }
+}
+
+impl Eq for NameItem {}
+
+impl Hash for NameItem {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.weight.to_bits().hash(state);
+    }
 }
 
 /// A batch of processed names ready for neural network training.
@@ -33,6 +80,63 @@ pub struct NameBatch {
     /// Contains the "next character" for each position in the input,
     /// shifted by one position (target[i] = input[i+1])
     pub targets: Tensor,
+
+    /// Value filling the tail of sequences shorter than the longest name in the batch
+    pub pad_index: i64,
+}
+
+impl NameBatch {
+    /// Reconstructs the batched names from the `chars` tensor, stripping padding.
+    ///
+    /// Useful for checking that batching round-trips. Each row is read up to the first
+    /// padding value.
+    ///
+    /// # Arguments
+    /// * `vocab` - Vocabulary the names are expected to be drawn from
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidArgument`] if a value is not a valid character
+    /// * [`MakemoreError::NotInVocabulary`] if a name does not encode with `vocab`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::data::{NameBatcher, NameItem};
+    /// use makemore_rs::vocabulary::Vocabulary;
+    ///
+    /// let names = vec![NameItem::new("emma"), NameItem::new("ava"), NameItem::new("isabella")];
+    /// let vocab = Vocabulary::new(&names);
+    /// let batch = NameBatcher::new(Device::Cpu).batch(names.clone())?;
+    ///
+    /// assert_eq!(batch.to_strings(&vocab)?, vec!["emma", "ava", "isabella"]);
+    /// # Ok::<(), makemore_rs::MakemoreError>(())
+    /// ```
+    pub fn to_strings(&self, vocab: &Vocabulary) -> Result<Vec<String>, MakemoreError> {
+        self.chars
+            .to_vec2::<i64>()?
+            .into_iter()
+            .map(|row| {
+                let name = row
+                    .into_iter()
+                    .take_while(|&c| c != self.pad_index)
+                    .map(|c| {
+                        u32::try_from(c)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| {
+                                MakemoreError::InvalidArgument(format!(
+                                    "{} is not a valid character",
+                                    c
+                                ))
+                            })
+                    })
+                    .collect::<Result<String, MakemoreError>>()?;
+                vocab.encode(&name)?;
+                Ok(name)
+            })
+            .collect()
+    }
 }
 
 /// Handles the conversion of name data into batched tensors for neural network training.
@@ -43,6 +147,8 @@ pub struct NameBatch {
 pub struct NameBatcher {
     /// The device (CPU/GPU) where the tensors will be allocated
     device: Device,
+    /// Value written to the padded tail of both input and target sequences
+    pad_index: i64,
 }
 
 impl NameBatcher {
@@ -51,7 +157,39 @@ impl NameBatcher {
     /// # Arguments
     /// * `device` - The device (CPU/GPU) where the tensors should be allocated
     pub fn new(device: Device) -> Self {
-        Self { device }
+        Self {
+            device,
+            pad_index: 0,
+        }
+    }
+
+    /// Sets the value used to pad shorter names, 0 by default.
+    ///
+    /// Choose a value no real character maps to, so padding can be told apart from data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::data::{NameBatcher, NameItem};
+    ///
+    /// let batcher = NameBatcher::new(Device::Cpu).with_pad_index(-1);
+    /// let batch = batcher.batch(vec![NameItem::new("ab"), NameItem::new("abcd")])?;
+    ///
+    /// let chars = batch.chars.to_vec2::<i64>()?;
+    /// let targets = batch.targets.to_vec2::<i64>()?;
+    /// assert_eq!(chars[0], vec!['a' as i64, 'b' as i64, -1, -1]);
+    /// assert_eq!(targets[0], vec!['b' as i64, -1, -1, -1]);
+    /// assert!(!chars[1].contains(&-1));
+    /// # Ok::<(), makemore_rs::MakemoreError>(())
+    /// ```
+    pub fn with_pad_index(mut self, pad_index: i64) -> Self {
+        self.pad_index = pad_index;
+        self
+    }
+
+    pub fn get_pad_index(&self) -> i64 {
+        self.pad_index
     }
 
     /// Converts a vector of NameItems into a batched tensor format suitable for training.
@@ -61,14 +199,14 @@ impl NameBatcher {
     /// 2. Converts characters to numeric values
     /// 3. Creates input tensors where each element predicts the next character
     /// 4. Creates target tensors shifted by one position
-    /// 5. Handles padding for names of different lengths
+    /// 5. Pads names shorter than the longest with the padding index
     ///
     /// # Arguments
     /// * `items` - Vector of NameItems to batch
     ///
     /// # Returns
-    /// * `Result<NameBatch>` - The processed batch with input and target tensors
-    pub fn batch(&self, items: Vec<NameItem>) -> Result<NameBatch> {
+    /// * `Result<NameBatch, MakemoreError>` - The processed batch with input and target tensors
+    pub fn batch(&self, items: Vec<NameItem>) -> Result<NameBatch, MakemoreError> {
         let max_len = items.iter().map(|item| item.name.len()).max().unwrap_or(0);
         info!("Max length: {}", max_len);
 
@@ -82,8 +220,8 @@ impl NameBatcher {
                     debug!("Processing item {} of {}", idx, items.len());
                 }
 
-                let mut char_seq = vec![0i64; max_len];
-                let mut target_seq = vec![0i64; max_len];
+                let mut char_seq = vec![self.pad_index; max_len];
+                let mut target_seq = vec![self.pad_index; max_len];
 
                 for (i, c) in item.name.chars().enumerate() {
                     char_seq[i] = c as i64;
@@ -102,14 +240,20 @@ impl NameBatcher {
         let chars = Tensor::from_vec(chars, (items.len(), max_len), &self.device)?;
         let targets = Tensor::from_vec(targets, (items.len(), max_len), &self.device)?;
 
-        Ok(NameBatch { chars, targets })
+        Ok(NameBatch {
+            chars,
+            targets,
+            pad_index: self.pad_index,
+        })
     }
 }
 
 /// Loads names from a text file into a vector of NameItems.
 ///
 /// Each line in the file is expected to contain a single name.
-/// Empty lines and whitespace are trimmed.
+/// Empty lines and whitespace are trimmed. Lines that are not valid UTF-8 are dropped,
+/// and the number of dropped lines is logged as a warning; use [`load_names_lossy`] to
+/// keep them.
 ///
 /// # Arguments
 /// * `path` - Path to the text file containing names
@@ -117,16 +261,194 @@ impl NameBatcher {
 /// # Returns
 /// * `Vec<NameItem>` - Vector of processed name items
 pub fn load_names(path: &str) -> Vec<NameItem> {
+    read_names(File::open(path).expect("Failed to open names file"))
+}
+
+/// Loads names from a text file like [`load_names`], keeping lines that are not valid
+/// UTF-8.
+///
+/// Each line is decoded with [`String::from_utf8_lossy`], so invalid byte sequences,
+/// e.g. from a Latin-1 encoded file, become U+FFFD replacement characters instead of
+/// the whole line being dropped.
+///
+/// # Arguments
+/// * `path` - Path to the text file containing names
+///
+/// # Returns
+/// * `Vec<NameItem>` - Vector of processed name items
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::data::{load_names, load_names_lossy};
+///
+/// // "zoë" encoded as Latin-1 is not valid UTF-8
+/// let path = std::env::temp_dir().join("makemore_latin1_names.txt");
+/// std::fs::write(&path, b"emma\nzo\xeb\nava\n").unwrap();
+///
+/// let names: Vec<String> = load_names_lossy(path.to_str().unwrap())
+///     .into_iter()
+///     .map(|item| item.name)
+///     .collect();
+/// assert_eq!(names, vec!["emma", "zo\u{FFFD}", "ava"]);
+/// assert_eq!(load_names(path.to_str().unwrap()).len(), 2);
+/// ```
+pub fn load_names_lossy(path: &str) -> Vec<NameItem> {
     BufReader::new(File::open(path).expect("Failed to open names file"))
-        .lines()
+        .split(b'\n')
         .filter_map(|line| {
-            line.ok().map(|l| NameItem {
-                name: l.trim().to_string(),
-            })
+            line.ok()
+                .map(|bytes| NameItem::new(String::from_utf8_lossy(&bytes).trim()))
         })
         .collect()
 }
 
+/// Loads names from a gzip-compressed text file, decompressing on the fly.
+///
+/// The decompressed content is read line by line exactly like [`load_names`].
+///
+/// # Arguments
+/// * `path` - Path to the gzip-compressed text file containing names
+///
+/// # Returns
+/// * `Vec<NameItem>` - Vector of processed name items
+///
+/// # Examples
+///
+/// ```
+/// use flate2::write::GzEncoder;
+/// use flate2::Compression;
+/// use makemore_rs::data::load_names_gz;
+/// use std::io::Write;
+///
+/// let path = std::env::temp_dir().join("makemore_names.txt.gz");
+/// let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+/// encoder.write_all(b"emma\nolivia\nava\n").unwrap();
+/// std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+///
+/// let names: Vec<String> = load_names_gz(path.to_str().unwrap())
+///     .into_iter()
+///     .map(|item| item.name)
+///     .collect();
+/// assert_eq!(names, vec!["emma", "olivia", "ava"]);
+/// ```
+#[cfg(feature = "flate2")]
+pub fn load_names_gz(path: &str) -> Vec<NameItem> {
+    let file = File::open(path).expect("Failed to open names file");
+    read_names(flate2::read::GzDecoder::new(file))
+}
+
+/// Reads one trimmed name per line from `reader`, warning about lines that fail to read.
+fn read_names(reader: impl Read) -> Vec<NameItem> {
+    let mut dropped = 0;
+    let names = BufReader::new(reader)
+        .lines()
+        .filter_map(|line| match line {
+            Ok(line) => Some(NameItem::new(line.trim())),
+            Err(_) => {
+                dropped += 1;
+                None
+            }
+        })
+        .collect();
+
+    warn_dropped_lines(dropped);
+    names
+}
+
+/// Logs how many lines a loader dropped because they could not be read as UTF-8.
+fn warn_dropped_lines(dropped: usize) {
+    if dropped > 0 {
+        warn!("Dropped {} lines that could not be read as UTF-8", dropped);
+    }
+}
+
+/// Loads names from a text file like [`load_names`], lowercasing each one.
+///
+/// Use this to treat the dataset as case-insensitive: "Emma" and "emma" become the same
+/// name, so a vocabulary built from the result has no casing collisions.
+///
+/// # Arguments
+/// * `path` - Path to the text file containing names
+///
+/// # Returns
+/// * `Vec<NameItem>` - Vector of lowercased name items
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::data::load_names_lowercased;
+/// use makemore_rs::vocabulary::Vocabulary;
+///
+/// let path = std::env::temp_dir().join("makemore_mixed_case_names.txt");
+/// std::fs::write(&path, "Emma\nemma\nAVA\n").unwrap();
+///
+/// let names = load_names_lowercased(path.to_str().unwrap());
+/// assert_eq!(names[0], names[1]);
+///
+/// let vocab = Vocabulary::new(&names);
+/// assert_eq!(vocab.get_chars(), &vec![".", "a", "e", "m", "v"]);
+/// assert!(vocab.casing_collisions().is_empty());
+/// ```
+pub fn load_names_lowercased(path: &str) -> Vec<NameItem> {
+    load_names(path)
+        .into_iter()
+        .map(|item| NameItem::with_weight(item.name.to_lowercase(), item.weight))
+        .collect()
+}
+
+/// Loads weighted names from a text file into a vector of NameItems.
+///
+/// Each line is expected to contain a name followed by a comma and its weight,
+/// e.g. `emma,15000`. Lines without a comma get the default weight of 1.0, and
+/// lines whose weight cannot be parsed are skipped with a warning. Lines that are not
+/// valid UTF-8 are dropped, and their number is logged as a warning.
+///
+/// # Arguments
+/// * `path` - Path to the text file containing `name,weight` lines
+///
+/// # Returns
+/// * `Vec<NameItem>` - Vector of processed name items with their weights
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::data::load_names_weighted;
+///
+/// let path = std::env::temp_dir().join("makemore_weighted_names.txt");
+/// std::fs::write(&path, b"emma,2\nzo\xeb,1\nava\n").unwrap();
+///
+/// let names = load_names_weighted(path.to_str().unwrap());
+/// assert_eq!(names.len(), 2);
+/// assert_eq!((names[0].name.as_str(), names[0].weight), ("emma", 2.0));
+/// assert_eq!((names[1].name.as_str(), names[1].weight), ("ava", 1.0));
+/// ```
+pub fn load_names_weighted(path: &str) -> Vec<NameItem> {
+    let mut dropped = 0;
+    let names = BufReader::new(File::open(path).expect("Failed to open names file"))
+        .lines()
+        .filter_map(|line| {
+            let Ok(line) = line else {
+                dropped += 1;
+                return None;
+            };
+            match line.rsplit_once(',') {
+                Some((name, weight)) => match weight.trim().parse::<f32>() {
+                    Ok(weight) => Some(NameItem::with_weight(name.trim(), weight)),
+                    Err(_) => {
+                        warn!("Skipping line with invalid weight: {}", line);
+                        None
+                    }
+                },
+                None => Some(NameItem::new(line.trim())),
+            }
+        })
+        .collect();
+
+    warn_dropped_lines(dropped);
+    names
+}
+
 pub fn load_names_unique(path: &str) -> Vec<NameItem> {
     load_names(path)
         .into_iter()
@@ -134,3 +456,182 @@ pub fn load_names_unique(path: &str) -> Vec<NameItem> {
         .into_iter()
         .collect()
 }
+
+/// Loads names from several text files, concatenated in file order.
+///
+/// Each file is read with [`load_names`]. When `dedup` is set, only the first
+/// occurrence of each name is kept, preserving the original order.
+///
+/// # Arguments
+/// * `paths` - Paths to the text files containing names
+/// * `dedup` - Whether to drop repeated names
+///
+/// # Returns
+/// * `Vec<NameItem>` - Vector of processed name items from all files
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::data::load_names_multi;
+///
+/// let dir = std::env::temp_dir();
+/// let first = dir.join("makemore_first_names.txt");
+/// let last = dir.join("makemore_last_names.txt");
+/// std::fs::write(&first, "emma\nava\n").unwrap();
+/// std::fs::write(&last, "smith\nemma\n").unwrap();
+///
+/// let paths = [first.to_str().unwrap(), last.to_str().unwrap()];
+/// let names: Vec<String> = load_names_multi(&paths, false)
+///     .into_iter()
+///     .map(|item| item.name)
+///     .collect();
+/// assert_eq!(names, vec!["emma", "ava", "smith", "emma"]);
+/// assert_eq!(load_names_multi(&paths, true).len(), 3);
+/// ```
+pub fn load_names_multi(paths: &[&str], dedup: bool) -> Vec<NameItem> {
+    let names = paths.iter().flat_map(|path| load_names(path));
+    if dedup {
+        let mut seen = std::collections::HashSet::new();
+        names.filter(|item| seen.insert(item.clone())).collect()
+    } else {
+        names.collect()
+    }
+}
+
+/// Loads a uniform random subset of `k` names from a text file in a single pass.
+///
+/// Uses reservoir sampling, so only `k` names are held in memory no matter how large
+/// the file is. Every line has the same probability `k / n` of being included, and the
+/// same `seed` always selects the same subset. Files with at most `k` lines are returned
+/// whole. The order of the returned names is not meaningful. Lines that are not valid
+/// UTF-8 are skipped rather than ending the file early, and their number is logged as a
+/// warning.
+///
+/// # Arguments
+/// * `path` - Path to the text file containing names
+/// * `k` - Number of names to keep
+/// * `seed` - Seed for the random number generator
+///
+/// # Returns
+/// * `Vec<NameItem>` - Vector of at most `k` name items
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::data::load_names_sample;
+/// use std::collections::HashMap;
+///
+/// let path = std::env::temp_dir().join("makemore_sample_names.txt");
+/// std::fs::write(&path, "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n").unwrap();
+/// let path = path.to_str().unwrap();
+///
+/// // Each of the 10 lines should be picked about 3 / 10 of the time
+/// let mut inclusions: HashMap<String, usize> = HashMap::new();
+/// for seed in 0..2000 {
+///     let sample = load_names_sample(path, 3, seed);
+///     assert_eq!(sample.len(), 3);
+///     for item in sample {
+///         *inclusions.entry(item.name).or_insert(0) += 1;
+///     }
+/// }
+/// assert_eq!(inclusions.len(), 10);
+/// assert!(inclusions.values().all(|&count| (450..750).contains(&count)));
+///
+/// // An invalid line in the middle does not hide the lines after it
+/// let path = std::env::temp_dir().join("makemore_sample_invalid_names.txt");
+/// std::fs::write(&path, b"a\n\xff\nb\n").unwrap();
+/// let mut names: Vec<String> = load_names_sample(path.to_str().unwrap(), 10, 0)
+///     .into_iter()
+///     .map(|item| item.name)
+///     .collect();
+/// names.sort();
+/// assert_eq!(names, vec!["a", "b"]);
+/// ```
+pub fn load_names_sample(path: &str, k: usize, seed: u64) -> Vec<NameItem> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut reservoir = Vec::with_capacity(k);
+
+    let mut dropped = 0;
+    let lines = BufReader::new(File::open(path).expect("Failed to open names file"))
+        .lines()
+        .filter_map(|line| match line {
+            Ok(line) => Some(line),
+            Err(_) => {
+                dropped += 1;
+                None
+            }
+        });
+    for (i, line) in lines.enumerate() {
+        let item = NameItem::new(line.trim());
+        if i < k {
+            reservoir.push(item);
+        } else {
+            let j = rng.gen_range(0..=i);
+            if j < k {
+                reservoir[j] = item;
+            }
+        }
+    }
+
+    warn_dropped_lines(dropped);
+    debug!("Sampled {} names from {}", reservoir.len(), path);
+    reservoir
+}
+
+/// Shuffles names in place with a seeded random number generator.
+///
+/// The same seed always produces the same permutation, so experiments that shuffle
+/// their data stay repeatable.
+///
+/// # Arguments
+/// * `names` - Name items to shuffle
+/// * `seed` - Seed for the random number generator
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::data::{shuffle_names, NameItem};
+///
+/// let names: Vec<NameItem> = (0..20).map(|i| NameItem::new(format!("name{}", i))).collect();
+///
+/// let mut first = names.clone();
+/// let mut second = names.clone();
+/// shuffle_names(&mut first, 42);
+/// shuffle_names(&mut second, 42);
+/// assert_eq!(first, second);
+///
+/// let mut other = names.clone();
+/// shuffle_names(&mut other, 7);
+/// assert_ne!(first, other);
+/// ```
+pub fn shuffle_names(names: &mut [NameItem], seed: u64) {
+    names.shuffle(&mut StdRng::seed_from_u64(seed));
+}
+
+/// Counts how many names there are of each length.
+///
+/// Lengths are measured in characters. This is a quick sanity check of a dataset
+/// before training, e.g. to choose a context size.
+///
+/// # Arguments
+/// * `names` - Slice of name items to measure
+///
+/// # Returns
+/// * `BTreeMap<usize, usize>` - Map from name length to the number of names with that length
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::data::{name_length_histogram, NameItem};
+///
+/// let names: Vec<NameItem> = ["emma", "ava", "anna", "bo"].into_iter().map(NameItem::new).collect();
+/// let histogram = name_length_histogram(&names);
+///
+/// assert_eq!(histogram.into_iter().collect::<Vec<_>>(), vec![(2, 1), (3, 1), (4, 2)]);
+/// ```
+pub fn name_length_histogram(names: &[NameItem]) -> BTreeMap<usize, usize> {
+    names.iter().fold(BTreeMap::new(), |mut histogram, item| {
+        *histogram.entry(item.name.chars().count()).or_insert(0) += 1;
+        histogram
+    })
+}
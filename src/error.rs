@@ -0,0 +1,60 @@
+//! Error type returned by the public API of the crate.
+
+use plotters::drawing::DrawingAreaErrorKind;
+use thiserror::Error;
+
+/// Errors produced by the models, the vocabulary, the data helpers and the plots.
+///
+/// Unlike a boxed error, the variants can be matched on to handle specific failures.
+#[derive(Debug, Error)]
+pub enum MakemoreError {
+    /// A character outside the '.' and a-z alphabet
+    #[error("Unexpected character: {0:?}")]
+    UnknownCharacter(char),
+
+    /// A token that is not part of the model's vocabulary
+    #[error("{0:?} is not in the vocabulary")]
+    NotInVocabulary(String),
+
+    /// An input that must contain at least one element was empty
+    #[error("{0} is empty")]
+    EmptyInput(&'static str),
+
+    /// An index outside `0..size`
+    #[error("Index {index} is out of range for size {size}")]
+    IndexOutOfRange { index: i64, size: usize },
+
+    /// A tensor did not have the expected shape
+    #[error("Shape mismatch: expected {expected}, got {actual}")]
+    ShapeMismatch { expected: String, actual: String },
+
+    /// Probabilities that cannot be sampled from, e.g. all zero or containing NaN
+    #[error("Invalid probabilities: {0}")]
+    InvalidProbabilities(String),
+
+    /// A parameter outside its valid range, e.g. a non-positive temperature
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    /// An operation that the selected device does not support
+    #[error("Unsupported operation: {0}")]
+    Unsupported(String),
+
+    /// A failure while drawing or saving a plot
+    #[error("Plotting failed: {0}")]
+    Plot(String),
+
+    /// A failure while serializing a model to disk
+    #[error("Serialization failed: {0}")]
+    Serialization(String),
+
+    /// An error raised by a candle tensor operation
+    #[error(transparent)]
+    Candle(#[from] candle_core::Error),
+}
+
+impl<E: std::error::Error + Send + Sync> From<DrawingAreaErrorKind<E>> for MakemoreError {
+    fn from(e: DrawingAreaErrorKind<E>) -> Self {
+        MakemoreError::Plot(e.to_string())
+    }
+}
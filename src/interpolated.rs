@@ -0,0 +1,173 @@
+//! Interpolated model that smooths sparse bigram statistics by mixing them with
+//! unigram frequencies.
+
+use crate::bigrams::{BigramModel, MAX_SAMPLE_LEN};
+use crate::data::NameItem;
+use crate::vocabulary::Vocabulary;
+use crate::MakemoreError;
+use candle_core::{Device, IndexOp, Tensor};
+use rand::Rng;
+
+/// Mixes bigram and unigram probabilities as `lambda * p_bi + (1 - lambda) * p_uni`.
+///
+/// The unigram distribution is the frequency of each token as the second element of a
+/// bigram, so it covers every character plus the '.' end token. Contexts that never
+/// occur in the training data have no bigram distribution and back off entirely to the
+/// unigram distribution.
+#[derive(Debug, Clone)]
+pub struct InterpolatedModel {
+    bigram: BigramModel,
+    lambda: f32,
+    /// Unigram probabilities of shape `[vocab_size]`
+    unigram: Tensor,
+    /// Interpolated probabilities of shape `[vocab_size, vocab_size]`
+    probabilities: Tensor,
+}
+
+impl InterpolatedModel {
+    /// Creates a new InterpolatedModel from the bigram and unigram counts of `names`
+    ///
+    /// # Arguments
+    /// * `names` - Slice of name items used to build the vocabulary
+    /// * `lambda` - Weight of the bigram distribution, between 0 and 1
+    /// * `device` - Device to store tensors on (CPU/GPU)
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidArgument`] if `lambda` is outside `[0, 1]`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::interpolated::InterpolatedModel;
+    ///
+    /// let names = vec![NameItem::new("emma"), NameItem::new("ava")];
+    /// let model = InterpolatedModel::new(&names, 0.0, &Device::Cpu)?;
+    ///
+    /// // Without any bigram weight every context predicts the unigram distribution
+    /// let unigram = model.get_unigram().to_vec1::<f32>()?;
+    /// for row in model.get_probabilities().to_vec2::<f32>()? {
+    ///     assert_eq!(row, unigram);
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn new(names: &[NameItem], lambda: f32, device: &Device) -> Result<Self, MakemoreError> {
+        if !(0.0..=1.0).contains(&lambda) {
+            return Err(MakemoreError::InvalidArgument(format!(
+                "Interpolation weight must be between 0 and 1, got {}",
+                lambda
+            )));
+        }
+
+        let bigram = BigramModel::new(names, device)?;
+        let counts = bigram.get_tensor().to_vec2::<f32>()?;
+        let vocab_size = counts.len();
+
+        let mut unigram = vec![0f32; vocab_size];
+        for row in &counts {
+            for (j, &count) in row.iter().enumerate() {
+                unigram[j] += count;
+            }
+        }
+        let total: f32 = unigram.iter().sum();
+        if total > 0.0 {
+            unigram.iter_mut().for_each(|p| *p /= total);
+        }
+
+        let bigram_probs = bigram.get_probabilities().to_vec2::<f32>()?;
+        let mut probabilities = Vec::with_capacity(vocab_size * vocab_size);
+        for (row, probs) in counts.iter().zip(&bigram_probs) {
+            if row.iter().sum::<f32>() > 0.0 {
+                probabilities.extend(
+                    probs
+                        .iter()
+                        .zip(&unigram)
+                        .map(|(&p_bi, &p_uni)| lambda * p_bi + (1.0 - lambda) * p_uni),
+                );
+            } else {
+                probabilities.extend_from_slice(&unigram);
+            }
+        }
+
+        Ok(Self {
+            bigram,
+            lambda,
+            unigram: Tensor::from_vec(unigram, vocab_size, device)?,
+            probabilities: Tensor::from_vec(probabilities, (vocab_size, vocab_size), device)?,
+        })
+    }
+
+    pub fn get_bigram(&self) -> &BigramModel {
+        &self.bigram
+    }
+
+    pub fn get_vocabulary(&self) -> &Vocabulary {
+        self.bigram.get_vocabulary()
+    }
+
+    pub fn get_lambda(&self) -> f32 {
+        self.lambda
+    }
+
+    pub fn get_unigram(&self) -> &Tensor {
+        &self.unigram
+    }
+
+    pub fn get_probabilities(&self) -> &Tensor {
+        &self.probabilities
+    }
+
+    /// Returns the interpolated probability of `b` following `a`.
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if either character is not in the vocabulary
+    pub fn probability(&self, a: &str, b: &str) -> Result<f32, MakemoreError> {
+        let i = self.get_vocabulary().get_index(a)?;
+        let j = self.get_vocabulary().get_index(b)?;
+        Ok(self.probabilities.i((i, j))?.to_scalar::<f32>()?)
+    }
+
+    /// Samples a single name from the interpolated distribution. The end token cannot be
+    /// drawn on the first step, so names are never empty, and names stop after 50
+    /// characters, as with [`BigramModel::sample_name`].
+    ///
+    /// # Arguments
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * The generated name without boundary tokens
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidProbabilities`] if a probability row reached while sampling
+    ///   has no mass or holds a negative or NaN value
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::interpolated::InterpolatedModel;
+    ///
+    /// // 'a' almost always follows 'a', which would run far past the cap
+    /// let names = vec![NameItem::new("a".repeat(200))];
+    /// let model = InterpolatedModel::new(&names, 0.9, &Device::Cpu)?;
+    ///
+    /// let mut rng = rand::thread_rng();
+    /// for _ in 0..20 {
+    ///     let name = model.sample_name(&mut rng)?;
+    ///     assert!((1..=50).contains(&name.chars().count()));
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn sample_name<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<String, MakemoreError> {
+        let (name, _) = BigramModel::sample_walk(
+            &self.probabilities,
+            self.get_vocabulary(),
+            0,
+            MAX_SAMPLE_LEN,
+            rng,
+        )?;
+        Ok(name)
+    }
+}
@@ -1,26 +1,50 @@
 use candle_core::{DType, Device, IndexOp, Tensor};
-use rand::distributions::Distribution;
 
 pub mod bigrams;
 pub mod data;
+pub mod error;
+pub mod interpolated;
+pub mod neural;
 pub mod plot;
+pub mod positional;
+pub mod tokenizer;
 pub mod utils;
 pub mod vocabulary;
 
+pub use error::MakemoreError;
+
 /// Creates bigram pairs of consecutive characters from input words, converting them to indices
 ///
 /// Each word is padded with '.' at start and end. Characters are converted to indices where:
 /// - '.' = 0
 /// - 'a' to 'z' = 1 to 26
 ///
+/// Empty input produces empty vectors, and empty words are skipped rather than
+/// producing a spurious '.' -> '.' pair.
+///
 /// # Arguments
 /// * `words` - Slice of strings to process
 ///
 /// # Returns
 /// * Tuple of (input indices, target indices) for training
-pub fn create_character_pairs(
-    words: &[String],
-) -> Result<(Vec<i64>, Vec<i64>), Box<dyn std::error::Error>> {
+///
+/// # Examples
+/// ```
+/// use makemore_rs::create_character_pairs;
+///
+/// assert_eq!(create_character_pairs(&[])?, (vec![], vec![]));
+/// assert_eq!(create_character_pairs(&["".to_string()])?, (vec![], vec![]));
+///
+/// let (xs, ys) = create_character_pairs(&["ab".to_string(), "".to_string()])?;
+/// assert_eq!(xs.len(), 3);
+/// assert_eq!(ys.len(), 3);
+/// # Ok::<(), makemore_rs::MakemoreError>(())
+/// ```
+pub fn create_character_pairs(words: &[String]) -> Result<(Vec<i64>, Vec<i64>), MakemoreError> {
+    if words.is_empty() {
+        return Ok((vec![], vec![]));
+    }
+
     let mut xs = Vec::new();
     let mut ys = Vec::new();
 
@@ -38,8 +62,8 @@ pub fn create_character_pairs(
         .collect();
     stoi.insert('.', 0); // Add start/end token
 
-    // Process each word
-    for word in words {
+    // Process each word, skipping empty ones
+    for word in words.iter().filter(|word| !word.is_empty()) {
         // Add start/end tokens
         let chars: Vec<char> = std::iter::once('.')
             .chain(word.chars())
@@ -58,6 +82,125 @@ pub fn create_character_pairs(
     Ok((xs, ys))
 }
 
+/// Counts how often each `(x, y)` index pair occurs
+///
+/// Turns the output of [`create_character_pairs`] into the same kind of count matrix the
+/// bigram model normalizes into probabilities: entry `[x][y]` is the number of times `x`
+/// is followed by `y`. Every pair counts once; see [`counts_from_weighted_pairs`] for
+/// weighted counts.
+///
+/// # Arguments
+/// * `xs` - Indices of the first element of each pair
+/// * `ys` - Indices of the second element of each pair
+/// * `vocab_size` - Number of possible indices
+/// * `device` - Device to store tensors on (CPU/GPU)
+///
+/// # Returns
+/// * `f32` tensor of shape `[vocab_size, vocab_size]`
+///
+/// # Errors
+/// * [`MakemoreError::ShapeMismatch`] if `xs` and `ys` have different lengths
+/// * [`MakemoreError::IndexOutOfRange`] if an index is negative or not below `vocab_size`
+///
+/// # Examples
+/// ```
+/// use candle_core::Device;
+/// use makemore_rs::counts_from_pairs;
+///
+/// let xs = [0i64, 1, 1, 2];
+/// let ys = [1i64, 2, 2, 0];
+/// let counts = counts_from_pairs(&xs, &ys, 3, &Device::Cpu)?;
+///
+/// assert_eq!(
+///     counts.to_vec2::<f32>()?,
+///     vec![vec![0.0, 1.0, 0.0], vec![0.0, 0.0, 2.0], vec![1.0, 0.0, 0.0]]
+/// );
+/// assert!(counts_from_pairs(&[0, 3], &[1, 1], 3, &Device::Cpu).is_err());
+/// # Ok::<(), makemore_rs::MakemoreError>(())
+/// ```
+pub fn counts_from_pairs(
+    xs: &[i64],
+    ys: &[i64],
+    vocab_size: usize,
+    device: &Device,
+) -> Result<Tensor, MakemoreError> {
+    counts_from_weighted_pairs(xs, ys, &vec![1.0; xs.len()], vocab_size, device)
+}
+
+/// Sums the weight of each `(x, y)` index pair into a count matrix
+///
+/// Works like [`counts_from_pairs`], but pair `k` adds `weights[k]` instead of 1, which is
+/// how the bigram model counts weighted names. The pairs are flattened to
+/// `x * vocab_size + y` and accumulated with a single `scatter_add`.
+///
+/// # Arguments
+/// * `xs` - Indices of the first element of each pair
+/// * `ys` - Indices of the second element of each pair
+/// * `weights` - Amount each pair adds to its count
+/// * `vocab_size` - Number of possible indices
+/// * `device` - Device to store tensors on (CPU/GPU)
+///
+/// # Errors
+/// * [`MakemoreError::ShapeMismatch`] if `xs`, `ys` and `weights` have different lengths
+/// * [`MakemoreError::IndexOutOfRange`] if an index is negative or not below `vocab_size`
+///
+/// # Examples
+/// ```
+/// use candle_core::Device;
+/// use makemore_rs::counts_from_weighted_pairs;
+///
+/// let counts = counts_from_weighted_pairs(&[0, 1, 1], &[1, 0, 0], &[0.5, 2.0, 1.0], 2, &Device::Cpu)?;
+/// assert_eq!(counts.to_vec2::<f32>()?, vec![vec![0.0, 0.5], vec![3.0, 0.0]]);
+/// # Ok::<(), makemore_rs::MakemoreError>(())
+/// ```
+pub fn counts_from_weighted_pairs(
+    xs: &[i64],
+    ys: &[i64],
+    weights: &[f32],
+    vocab_size: usize,
+    device: &Device,
+) -> Result<Tensor, MakemoreError> {
+    if weights.len() != xs.len() {
+        return Err(MakemoreError::ShapeMismatch {
+            expected: format!("{} weights", xs.len()),
+            actual: format!("{} weights", weights.len()),
+        });
+    }
+    if xs.len() != ys.len() {
+        return Err(MakemoreError::ShapeMismatch {
+            expected: format!("{} targets", xs.len()),
+            actual: format!("{} targets", ys.len()),
+        });
+    }
+    if let Some(&index) = xs
+        .iter()
+        .chain(ys)
+        .find(|&&ix| ix < 0 || ix as usize >= vocab_size)
+    {
+        return Err(MakemoreError::IndexOutOfRange {
+            index,
+            size: vocab_size,
+        });
+    }
+
+    let flat: Vec<i64> = xs
+        .iter()
+        .zip(ys)
+        .map(|(&x, &y)| x * vocab_size as i64 + y)
+        .collect();
+    let counts = Tensor::zeros(vocab_size * vocab_size, DType::F32, device)?;
+    if flat.is_empty() {
+        return Ok(counts.reshape((vocab_size, vocab_size))?);
+    }
+
+    let indices = Tensor::from_vec(flat, xs.len(), device)?;
+    let weights = Tensor::from_slice(weights, xs.len(), device)?;
+    let counts = counts
+        .scatter_add(&indices, &weights, 0)?
+        .reshape((vocab_size, vocab_size))?;
+    Ok(counts)
+}
+
 /// Creates one-hot encoded vectors from input indices
 ///
 /// One-hot encoding converts categorical data (like character indices) into a binary vector format
@@ -70,6 +213,9 @@ pub fn create_character_pairs(
 /// - Make independent predictions for each possible class
 /// - Avoid imposing artificial ordering between categories
 ///
+/// The vectors are F32 so they can be fed straight into a matmul; use
+/// [`create_one_hot_encoding_with_dtype`] for another element type.
+///
 /// # Arguments
 /// * `xs` - Input tensor containing indices
 /// * `num_classes` - Number of possible classes (vocabulary size)
@@ -77,14 +223,88 @@ pub fn create_character_pairs(
 ///
 /// # Returns
 /// * Tensor of one-hot encoded vectors
+///
+/// # Errors
+/// * [`MakemoreError::ShapeMismatch`] if `xs` is not one-dimensional
+///
+/// # Examples
+///
+/// ```
+/// use candle_core::{DType, Device, Tensor};
+/// use makemore_rs::create_one_hot_encoding;
+///
+/// let device = Device::Cpu;
+/// let xs = Tensor::new(&[0i64, 2, 1], &device)?;
+/// let xenc = create_one_hot_encoding(&xs, 3, &device)?;
+///
+/// assert_eq!(xenc.dtype(), DType::F32);
+/// assert_eq!(
+///     xenc.to_vec2::<f32>()?,
+///     vec![vec![1.0, 0.0, 0.0], vec![0.0, 0.0, 1.0], vec![0.0, 1.0, 0.0]]
+/// );
+/// # Ok::<(), makemore_rs::MakemoreError>(())
+/// ```
 pub fn create_one_hot_encoding(
     xs: &Tensor,
     num_classes: usize,
     device: &Device,
-) -> Result<Tensor, Box<dyn std::error::Error>> {
-    let xs_zeros = Tensor::zeros((xs.dim(0)?, num_classes), DType::F32, device)?;
+) -> Result<Tensor, MakemoreError> {
+    create_one_hot_encoding_with_dtype(xs, num_classes, DType::F32, device)
+}
+
+/// Creates one-hot encoded vectors like [`create_one_hot_encoding`] with the given
+/// element type
+///
+/// # Arguments
+/// * `xs` - Input tensor containing indices
+/// * `num_classes` - Number of possible classes (vocabulary size)
+/// * `dtype` - Element type of the output; `DType::U8` keeps memory use down when the
+///   vectors are not fed into a matmul
+/// * `device` - Device to store tensors on (CPU/GPU)
+///
+/// # Errors
+/// * [`MakemoreError::ShapeMismatch`] if `xs` is not one-dimensional
+/// * [`MakemoreError::Unsupported`] if `dtype` is an integer type and `device` is Metal,
+///   whose `scatter_add` only supports floating point values
+///
+/// # Examples
+///
+/// ```
+/// use candle_core::{DType, Device, Tensor};
+/// use makemore_rs::create_one_hot_encoding_with_dtype;
+///
+/// let device = Device::Cpu;
+/// let xs = Tensor::new(&[0i64, 2, 1], &device)?;
+/// let xenc = create_one_hot_encoding_with_dtype(&xs, 3, DType::U8, &device)?;
+///
+/// assert_eq!(xenc.dtype(), DType::U8);
+/// assert_eq!(
+///     xenc.to_vec2::<u8>()?,
+///     vec![vec![1, 0, 0], vec![0, 0, 1], vec![0, 1, 0]]
+/// );
+/// # Ok::<(), makemore_rs::MakemoreError>(())
+/// ```
+pub fn create_one_hot_encoding_with_dtype(
+    xs: &Tensor,
+    num_classes: usize,
+    dtype: DType,
+    device: &Device,
+) -> Result<Tensor, MakemoreError> {
+    if xs.rank() != 1 {
+        return Err(MakemoreError::ShapeMismatch {
+            expected: "a 1-dimensional tensor of indices".to_string(),
+            actual: format!("{:?}", xs.shape()),
+        });
+    }
+    if device.is_metal() && !dtype.is_float() {
+        return Err(MakemoreError::Unsupported(format!(
+            "scatter_add does not support {:?} on Metal",
+            dtype
+        )));
+    }
+    let xs_zeros = Tensor::zeros((xs.dim(0)?, num_classes), dtype, device)?;
     let indices = xs.to_dtype(DType::I64)?.unsqueeze(1)?;
-    let ones = Tensor::ones(indices.shape(), DType::F32, device)?;
+    let ones = Tensor::ones(indices.shape(), dtype, device)?;
     let x_one_hot = xs_zeros.scatter_add(&indices, &ones, 1)?;
     Ok(x_one_hot)
 }
@@ -105,13 +325,27 @@ pub fn create_one_hot_encoding(
 /// # Returns
 /// * Index value as i64
 ///
-/// # Panics
-/// * If character is not '.' or lowercase a-z
-pub fn char_to_index(c: char) -> i64 {
+/// # Errors
+/// * [`MakemoreError::UnknownCharacter`] if the character is not '.' or lowercase a-z
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::{char_to_index, MakemoreError};
+///
+/// assert_eq!(char_to_index('.')?, 0);
+/// assert_eq!(char_to_index('e')?, 5);
+/// assert!(matches!(
+///     char_to_index('E'),
+///     Err(MakemoreError::UnknownCharacter('E'))
+/// ));
+/// # Ok::<(), MakemoreError>(())
+/// ```
+pub fn char_to_index(c: char) -> Result<i64, MakemoreError> {
     match c {
-        '.' => 0,
-        'a'..='z' => (c as u8 - b'a' + 1) as i64,
-        _ => panic!("Unexpected character: {}", c),
+        '.' => Ok(0),
+        'a'..='z' => Ok((c as u8 - b'a' + 1) as i64),
+        _ => Err(MakemoreError::UnknownCharacter(c)),
     }
 }
 
@@ -157,7 +391,7 @@ pub fn verify_matrix_multiplication(
     w: &Tensor,
     row_idx: usize,
     col_idx: usize,
-) -> Result<f32, Box<dyn std::error::Error>> {
+) -> Result<f32, MakemoreError> {
     let value = xenc_w.i(row_idx)?.i(col_idx)?;
 
     let row = xenc.i(row_idx)?.to_vec1::<f32>()?;
@@ -208,13 +442,42 @@ pub fn verify_matrix_multiplication(
 ///
 /// # Returns
 /// * Tensor of probabilities
-pub fn apply_softmax(logits: &Tensor) -> Result<Tensor, Box<dyn std::error::Error>> {
+pub fn apply_softmax(logits: &Tensor) -> Result<Tensor, MakemoreError> {
+    apply_softmax_dim(logits, 1)
+}
+
+/// Applies softmax along an arbitrary dimension
+///
+/// Works like [`apply_softmax`], but normalizes along `dim` instead of always using
+/// dimension 1, so it can be used on logits of any rank.
+///
+/// # Arguments
+/// * `logits` - Tensor of raw model outputs
+/// * `dim` - Dimension along which the probabilities sum to 1
+///
+/// # Returns
+/// * Tensor of probabilities with the same shape as `logits`
+///
+/// # Examples
+/// ```
+/// use candle_core::{Device, Tensor};
+/// use makemore_rs::apply_softmax_dim;
+///
+/// let logits = Tensor::new(&[[1f32, 2.0, 3.0], [0.5, -1.0, 2.0]], &Device::Cpu)?;
+/// let probs = apply_softmax_dim(&logits, 0)?;
+///
+/// for column_sum in probs.sum(0)?.to_vec1::<f32>()? {
+///     assert!((column_sum - 1.0).abs() < 1e-6);
+/// }
+/// # Ok::<(), makemore_rs::MakemoreError>(())
+/// ```
+pub fn apply_softmax_dim(logits: &Tensor, dim: usize) -> Result<Tensor, MakemoreError> {
     // Convert logits to exponential scale (all positive numbers)
     // Equivalent to N(w, x)
     let counts = logits.exp()?;
 
-    // Sum along dimension 1, keeping dimensions for broadcasting
-    let sum = counts.sum_keepdim(1)?;
+    // Sum along the requested dimension, keeping dimensions for broadcasting
+    let sum = counts.sum_keepdim(dim)?;
 
     // Broadcast sum to match counts shape for element-wise division
     let sum_broadcast = sum.broadcast_as(counts.shape())?;
@@ -225,6 +488,46 @@ pub fn apply_softmax(logits: &Tensor) -> Result<Tensor, Box<dyn std::error::Erro
     Ok(prob)
 }
 
+/// Applies softmax after excluding masked positions
+///
+/// Every position where `mask` is 1 has its logit replaced by negative infinity,
+/// so after the softmax it gets probability 0 and the remaining probability is spread
+/// over the allowed positions. This is how constrained generation forbids next
+/// characters, e.g. masking the '.' boundary token until a name reaches a minimum
+/// length. The softmax runs along the last dimension. If every position of a row is
+/// masked the row is all NaN.
+///
+/// # Arguments
+/// * `logits` - Tensor of raw model outputs
+/// * `mask` - Tensor of 0s and 1s, with 1 at forbidden positions, either the same shape as
+///   `logits` or broadcastable to it, e.g. one `[vocab_size]` mask for every row
+///
+/// # Returns
+/// * Tensor of probabilities with the same shape as `logits`
+///
+/// # Examples
+/// ```
+/// use candle_core::{Device, Tensor};
+/// use makemore_rs::apply_softmax_masked;
+///
+/// let device = Device::Cpu;
+/// let logits = Tensor::new(&[[2f32, 1.0, 0.5], [0.0, 3.0, 1.0]], &device)?;
+/// let mask = Tensor::new(&[1u8, 0, 0], &device)?;
+///
+/// for row in apply_softmax_masked(&logits, &mask)?.to_vec2::<f32>()? {
+///     assert_eq!(row[0], 0.0);
+///     assert!((row.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+/// }
+/// # Ok::<(), makemore_rs::MakemoreError>(())
+/// ```
+pub fn apply_softmax_masked(logits: &Tensor, mask: &Tensor) -> Result<Tensor, MakemoreError> {
+    let mask = mask.to_dtype(DType::U8)?.broadcast_as(logits.shape())?;
+    let forbidden = Tensor::full(f32::NEG_INFINITY, logits.shape(), logits.device())?
+        .to_dtype(logits.dtype())?;
+    let masked = mask.where_cond(&forbidden, logits)?;
+    apply_softmax_dim(&masked, logits.rank().saturating_sub(1))
+}
+
 /// Samples an index from a probability distribution
 ///
 /// # Arguments
@@ -232,14 +535,20 @@ pub fn apply_softmax(logits: &Tensor) -> Result<Tensor, Box<dyn std::error::Erro
 ///
 /// # Returns
 /// * Index of the sampled value
-pub fn sample_from_probs(probs: &Tensor) -> Result<usize, Box<dyn std::error::Error>> {
+///
+/// # Errors
+/// * [`MakemoreError::EmptyInput`] if `probs` has no elements
+/// * [`MakemoreError::InvalidProbabilities`] if the scaled probabilities cannot be sampled
+pub fn sample_from_probs(probs: &Tensor) -> Result<usize, MakemoreError> {
+    if probs.elem_count() == 0 {
+        return Err(MakemoreError::EmptyInput("Probability tensor"));
+    }
+
     let temperature = 0.8;
     let temp_tensor = Tensor::new(temperature, probs.device())?.unsqueeze(0)?;
     let scaled_logits = probs.log()?.div(&temp_tensor)?;
     let scaled_probs = apply_softmax(&scaled_logits)?;
 
     let prob_vec: Vec<f32> = scaled_probs.to_vec1()?;
-    let mut rng = rand::thread_rng();
-    let dist = rand::distributions::WeightedIndex::new(&prob_vec)?;
-    Ok(dist.sample(&mut rng))
+    utils::sample_from_probs(&prob_vec, &mut rand::thread_rng())
 }
@@ -1,7 +1,9 @@
-use candle_core::{DType, Device, IndexOp, Tensor, Var};
-use candle_nn::{Optimizer, SGD};
-use makemore_rs::{apply_softmax, create_character_pairs, create_one_hot_encoding, index_to_char};
-use rand::distributions::Distribution;
+use candle_core::{Device, Tensor};
+use makemore_rs::create_character_pairs;
+use makemore_rs::neural::NeuralBigramModel;
+use makemore_rs::utils::env_or;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
 /// Trains a simple character-level language model using stochastic gradient descent
 ///
@@ -25,9 +27,18 @@ use rand::distributions::Distribution;
 /// - Maximum likelihood training with cross-entropy loss
 /// - Gradient-based optimization
 ///
+/// # Environment
+/// * `MAKEMORE_STEPS` - Number of training steps (default 10)
+/// * `MAKEMORE_SAMPLES` - Number of names generated after training (default 5)
+/// * `MAKEMORE_SEED` - Seed of the sampling random number generator (default 2147483647)
+///
 /// # Returns
 /// * Result indicating success or error during training
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let steps: usize = env_or("MAKEMORE_STEPS", 10)?;
+    let samples: usize = env_or("MAKEMORE_SAMPLES", 5)?;
+    let seed: u64 = env_or("MAKEMORE_SEED", 2147483647)?;
+
     // Load training data
     let names = makemore_rs::data::load_names("./names.txt");
 
@@ -44,89 +55,25 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let ys_tensor = Tensor::new(ys, &device)?;
 
     // Initialize weight matrix with random values
-    // Using Var instead of Tensor enables automatic gradient tracking
     // Shape is (27,27) for transitions between all possible characters
-    let w = Var::randn(0.0, 1.0, (27, 27), &device)?;
-
-    // Create SGD optimizer to update weights
-    // Learning rate 50.0 controls size of weight updates
-    // Big for this simple model
-    let mut opt = SGD::new(vec![w.clone()], 50.0)?;
+    let mut model = NeuralBigramModel::new(27, &device)?;
 
     // Training loop - each iteration:
     // 1. Forward pass to get predictions
     // 2. Calculate loss
     // 3. Backprop gradients
     // 4. Update weights
-    for k in 0..10 {
-        // Convert input chars to one-hot vectors
-        // This creates a sparse binary matrix where each row has a single 1
-        let xenc = create_one_hot_encoding(&xs_tensor, 27, &device)?.to_dtype(DType::F32)?;
-
-        // Ensure weights are f32 for matmul
-        let w_f32 = w.to_dtype(DType::F32)?;
-
-        // Forward pass: multiply one-hot vectors by weights
-        // This computes raw logit scores for each possible next character
-        let logits = xenc.matmul(&w_f32)?;
-        // Convert logits to probabilities with softmax
-        // Now each row sums to 1 and represents a probability distribution
-        let probs = apply_softmax(&logits)?;
-
-        // Calculate negative log likelihood loss using batch operations
-        let indices = Tensor::arange(0, xs_tensor.dims()[0] as i64, &device)?;
-        let target_probs = probs
-            .index_select(&indices, 0)?
-            .gather(&ys_tensor.unsqueeze(1)?, 1)?
-            .squeeze(1)?;
-        let loss = target_probs.log()?.neg()?.mean_all()?;
-
-        // Add L2 regularization like in the Python version
-        let l2_loss = w_f32
-            .powf(2.0)?
-            .mean_all()?
-            .mul(&Tensor::new(0.01f32, &device)?)?;
-        let loss = loss.add(&l2_loss)?;
-
-        println!("Step {}, Loss: {}", k, loss.to_scalar::<f32>()?);
-
-        // Compute gradients and update weights with SGD
-        opt.backward_step(&loss)?;
+    // Learning rate 50.0 controls size of weight updates
+    // Big for this simple model
+    let losses = model.train(&xs_tensor, &ys_tensor, steps, 50.0)?;
+    for (k, loss) in losses.iter().enumerate() {
+        println!("Step {}, Loss: {}", k, loss);
     }
 
     // Generation loop
-    let mut rng = rand::thread_rng();
-    for _ in 0..5 {
-        let mut out = Vec::new();
-        let mut ix = 0; // Start with first character (.)
-
-        loop {
-            // Convert current character index to one-hot
-            let x_tensor = Tensor::new(&[ix as i64], &device)?;
-            let xenc = create_one_hot_encoding(&x_tensor, 27, &device)?.to_dtype(DType::F32)?;
-
-            // Get probabilities for next character
-            let logits = xenc.matmul(&w.to_dtype(DType::F32)?)?;
-            let probs = apply_softmax(&logits)?;
-
-            // Sample from probability distribution
-            // Squeeze to remove the extra dimension [1, 27] -> [27]
-            let prob_vec: Vec<f32> = probs.squeeze(0)?.to_vec1()?;
-            let dist = rand::distributions::WeightedIndex::new(&prob_vec)?;
-            ix = dist.sample(&mut rng);
-
-            // Convert index back to character and append
-            let c = index_to_char(ix);
-            out.push(c);
-
-            // Break if we generated end token or name is too long
-            if ix == 0 || out.len() > 20 {
-                break;
-            }
-        }
-
-        // Print generated name
-        println!("Generated: {}", out.into_iter().collect::<String>());
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..samples {
+        println!("Generated: {}", model.generate(&mut rng)?);
     }
 
     Ok(())
@@ -0,0 +1,1141 @@
+//! Neural network formulation of the bigram model.
+//!
+//! Instead of counting character pairs, this model learns a single weight matrix `W`
+//! that maps a one-hot encoded character to the logits of the next character. Trained
+//! with negative log likelihood it converges towards the same distribution as the
+//! count-based [`crate::bigrams::BigramModel`].
+
+use crate::utils::{mask_end_token, sample_from_probs};
+use crate::vocabulary::Vocabulary;
+use crate::{apply_softmax, create_one_hot_encoding, index_to_char, MakemoreError};
+use candle_core::{DType, Device, Shape, Tensor, Var};
+use candle_nn::{Optimizer, SGD};
+use rand::Rng;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use tracing::debug;
+
+/// Maximum number of characters produced by [`NeuralBigramModel::generate`]
+const MAX_GENERATED_LEN: usize = 20;
+
+/// Default L2 regularization coefficient, matching the Python version
+const DEFAULT_L2_STRENGTH: f64 = 0.01;
+
+/// Learning rate schedule applied by the training methods of [`NeuralBigramModel`].
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::neural::LrSchedule;
+///
+/// let step_decay = LrSchedule::StepDecay { gamma: 0.5, step_size: 2 };
+/// let rates: Vec<f64> = (0..6).map(|step| step_decay.learning_rate(8.0, step, 6)).collect();
+/// assert_eq!(rates, vec![8.0, 8.0, 4.0, 4.0, 2.0, 2.0]);
+///
+/// let warmup = LrSchedule::WarmupLinearDecay { warmup_steps: 2 };
+/// let rates: Vec<f64> = (0..6).map(|step| warmup.learning_rate(8.0, step, 6)).collect();
+/// assert_eq!(rates, vec![4.0, 8.0, 8.0, 6.0, 4.0, 2.0]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LrSchedule {
+    /// Use the base learning rate at every step
+    #[default]
+    Constant,
+    /// Multiply the learning rate by `gamma` every `step_size` steps:
+    /// `lr * gamma^(step / step_size)`
+    StepDecay { gamma: f64, step_size: usize },
+    /// Ramp up linearly over `warmup_steps`, then decay linearly towards zero at the
+    /// final step
+    WarmupLinearDecay { warmup_steps: usize },
+}
+
+impl LrSchedule {
+    /// Returns the learning rate to use at `step` out of `total_steps`
+    ///
+    /// # Arguments
+    /// * `base_lr` - Learning rate passed to the training method
+    /// * `step` - Zero-based index of the current step
+    /// * `total_steps` - Total number of steps training may run for
+    pub fn learning_rate(&self, base_lr: f64, step: usize, total_steps: usize) -> f64 {
+        match *self {
+            LrSchedule::Constant => base_lr,
+            LrSchedule::StepDecay { gamma, step_size } => {
+                base_lr * gamma.powi((step / step_size.max(1)) as i32)
+            }
+            LrSchedule::WarmupLinearDecay { warmup_steps } => {
+                if step < warmup_steps {
+                    base_lr * (step + 1) as f64 / warmup_steps as f64
+                } else {
+                    let decay_steps = total_steps.saturating_sub(warmup_steps).max(1);
+                    let remaining = total_steps.saturating_sub(step);
+                    base_lr * remaining as f64 / decay_steps as f64
+                }
+            }
+        }
+    }
+}
+
+/// A bigram language model expressed as a single linear layer followed by softmax.
+#[derive(Debug, Clone)]
+pub struct NeuralBigramModel {
+    /// Learnable `[vocab_size, vocab_size]` weight matrix
+    w: Var,
+    /// Number of characters in the vocabulary, including the '.' boundary token
+    vocab_size: usize,
+    /// Device the weights live on
+    device: Device,
+    /// Coefficient of the L2 penalty added to the loss
+    l2_strength: f64,
+    /// Learning rate schedule applied during training
+    lr_schedule: LrSchedule,
+    /// Decay of the exponential moving average of the weights, if enabled
+    ema_decay: Option<f64>,
+    /// Exponential moving average of the weights, maintained while training
+    ema_weights: Option<Tensor>,
+    /// Probability of zeroing each logit in training mode
+    dropout: f64,
+    /// Whether dropout is applied in the forward pass
+    training: bool,
+}
+
+impl NeuralBigramModel {
+    /// Creates a new model with random weights from [`kaiming_uniform`]
+    ///
+    /// Each one-hot input selects a single row of `W`, so the fan-in is `vocab_size`.
+    ///
+    /// # Arguments
+    /// * `vocab_size` - Number of characters in the vocabulary
+    /// * `device` - Device to store tensors on (CPU/GPU)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::neural::NeuralBigramModel;
+    ///
+    /// let model = NeuralBigramModel::new(27, &Device::Cpu)?;
+    /// let bound = (6.0f32 / 27.0).sqrt();
+    /// let w = model.get_weights().flatten_all()?.to_vec1::<f32>()?;
+    /// assert!(w.iter().all(|x| x.abs() <= bound));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn new(vocab_size: usize, device: &Device) -> Result<Self, MakemoreError> {
+        // Using Var instead of Tensor enables automatic gradient tracking
+        let w = kaiming_uniform((vocab_size, vocab_size), vocab_size, device)?;
+        Ok(Self {
+            w,
+            vocab_size,
+            device: device.clone(),
+            l2_strength: DEFAULT_L2_STRENGTH,
+            lr_schedule: LrSchedule::default(),
+            ema_decay: None,
+            ema_weights: None,
+            dropout: 0.0,
+            training: true,
+        })
+    }
+
+    /// Creates a model from an existing `[vocab_size, vocab_size]` weight matrix
+    ///
+    /// # Arguments
+    /// * `w` - Weight matrix where row `i` holds the logits for the character following `i`
+    pub fn from_weights(w: &Tensor) -> Result<Self, MakemoreError> {
+        let vocab_size = w.dim(0)?;
+        Ok(Self {
+            w: Var::from_tensor(w)?,
+            vocab_size,
+            device: w.device().clone(),
+            l2_strength: DEFAULT_L2_STRENGTH,
+            lr_schedule: LrSchedule::default(),
+            ema_decay: None,
+            ema_weights: None,
+            dropout: 0.0,
+            training: true,
+        })
+    }
+
+    /// Sets the L2 regularization coefficient used by the loss
+    ///
+    /// Larger values pull the weights towards zero, smoothing the learned distribution
+    /// towards uniform. `0.0` disables regularization. Defaults to `0.01`.
+    ///
+    /// # Arguments
+    /// * `l2_strength` - Coefficient multiplying the mean squared weight
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::{Device, Tensor};
+    /// use makemore_rs::neural::NeuralBigramModel;
+    ///
+    /// let device = Device::Cpu;
+    /// let xs = Tensor::new(&[0i64, 1, 2], &device)?;
+    /// let ys = Tensor::new(&[1i64, 2, 0], &device)?;
+    /// let w = Tensor::randn(0f32, 1f32, (27, 27), &device)?;
+    ///
+    /// let mut weak = NeuralBigramModel::from_weights(&w)?.with_l2_strength(0.0);
+    /// let mut strong = NeuralBigramModel::from_weights(&w)?.with_l2_strength(100.0);
+    /// weak.train(&xs, &ys, 50, 1.0)?;
+    /// strong.train(&xs, &ys, 50, 1.0)?;
+    ///
+    /// let weak_norm = weak.get_weights().abs()?.mean_all()?.to_scalar::<f32>()?;
+    /// let strong_norm = strong.get_weights().abs()?.mean_all()?.to_scalar::<f32>()?;
+    /// assert!(strong_norm < 0.1 * weak_norm);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn with_l2_strength(mut self, l2_strength: f64) -> Self {
+        self.l2_strength = l2_strength;
+        self
+    }
+
+    /// Sets the learning rate schedule used by the training methods
+    ///
+    /// The learning rate passed to a training method becomes the base rate of the
+    /// schedule, and the optimizer's rate is updated before every step. Defaults to
+    /// [`LrSchedule::Constant`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::{Device, Tensor};
+    /// use makemore_rs::neural::{LrSchedule, NeuralBigramModel};
+    ///
+    /// let device = Device::Cpu;
+    /// let xs = Tensor::new(&[0i64, 1], &device)?;
+    /// let ys = Tensor::new(&[1i64, 0], &device)?;
+    /// let schedule = LrSchedule::StepDecay { gamma: 0.5, step_size: 10 };
+    ///
+    /// let mut model = NeuralBigramModel::new(27, &device)?.with_lr_schedule(schedule);
+    /// let losses = model.train(&xs, &ys, 30, 10.0)?;
+    /// assert!(losses[29] < losses[0]);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn with_lr_schedule(mut self, lr_schedule: LrSchedule) -> Self {
+        self.lr_schedule = lr_schedule;
+        self
+    }
+
+    /// Enables an exponential moving average of the weights during training
+    ///
+    /// After every optimizer step the average is updated as
+    /// `ema = decay * ema + (1 - decay) * W`, starting from the weights before the
+    /// first step. The averaged weights are usually smoother than the raw ones and can be
+    /// swapped in for evaluation or generation with
+    /// [`NeuralBigramModel::swap_ema_weights`].
+    ///
+    /// # Arguments
+    /// * `decay` - Weight kept by the running average at each step, between 0 and 1
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::{Device, Tensor};
+    /// use makemore_rs::neural::NeuralBigramModel;
+    ///
+    /// let device = Device::Cpu;
+    /// let xs = Tensor::new(&[0i64, 1, 2], &device)?;
+    /// let ys = Tensor::new(&[1i64, 2, 0], &device)?;
+    /// let w0 = Tensor::randn(0f32, 1f32, (27, 27), &device)?;
+    /// let mut model = NeuralBigramModel::from_weights(&w0)?.with_ema(0.9);
+    ///
+    /// // After one step the average moved a tenth of the way towards the new weights
+    /// model.train(&xs, &ys, 1, 10.0)?;
+    /// let w1 = model.get_weights().copy()?;
+    /// let expected = ((&w0 * 0.9)? + (&w1 * 0.1)?)?;
+    /// let ema = model.get_ema_weights().unwrap();
+    /// let diff = (ema - &expected)?.abs()?.flatten_all()?.max(0)?.to_scalar::<f32>()?;
+    /// assert!(diff < 1e-5);
+    ///
+    /// // Over more steps it keeps tracking the weights, but lags behind them
+    /// model.train(&xs, &ys, 10, 10.0)?;
+    /// let distance = |t: &Tensor| -> candle_core::Result<f32> {
+    ///     (t - &w0)?.abs()?.mean_all()?.to_scalar::<f32>()
+    /// };
+    /// let ema_distance = distance(model.get_ema_weights().unwrap())?;
+    /// assert!(ema_distance > 0.0);
+    /// assert!(ema_distance < distance(model.get_weights())?);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn with_ema(mut self, decay: f64) -> Self {
+        self.ema_decay = Some(decay);
+        self
+    }
+
+    /// Sets the dropout probability applied to the logits in training mode
+    ///
+    /// See [`dropout`] for how elements are dropped. Defaults to `0.0`, which disables
+    /// dropout. A freshly created model is in training mode, so the forward pass and the
+    /// loss apply dropout until [`NeuralBigramModel::set_training`] switches it off.
+    /// Generation and the validation loss of [`NeuralBigramModel::train_early_stop`]
+    /// never apply dropout.
+    ///
+    /// # Arguments
+    /// * `p` - Probability of zeroing each logit, in `[0, 1)`
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidArgument`] if `p` is outside `[0, 1)`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::neural::NeuralBigramModel;
+    ///
+    /// let device = Device::Cpu;
+    /// assert!(NeuralBigramModel::new(27, &device)?.with_dropout(0.5).is_ok());
+    /// assert!(NeuralBigramModel::new(27, &device)?.with_dropout(1.0).is_err());
+    /// assert!(NeuralBigramModel::new(27, &device)?.with_dropout(-0.1).is_err());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn with_dropout(mut self, p: f64) -> Result<Self, MakemoreError> {
+        if !(0.0..1.0).contains(&p) {
+            return Err(MakemoreError::InvalidArgument(format!(
+                "Dropout probability must be in [0, 1), got {}",
+                p
+            )));
+        }
+        self.dropout = p;
+        Ok(self)
+    }
+
+    /// Switches between training mode, where dropout is applied, and evaluation mode,
+    /// where the forward pass is deterministic
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::{Device, Tensor};
+    /// use makemore_rs::neural::NeuralBigramModel;
+    ///
+    /// let device = Device::Cpu;
+    /// let xs = Tensor::new(&[0i64, 1, 2], &device)?;
+    /// let mut model = NeuralBigramModel::new(27, &device)?.with_dropout(0.5)?;
+    /// assert!(model.is_training());
+    ///
+    /// model.set_training(false);
+    /// let first = model.forward_logits(&xs)?.to_vec2::<f32>()?;
+    /// let second = model.forward_logits(&xs)?.to_vec2::<f32>()?;
+    /// assert_eq!(first, second);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    pub fn is_training(&self) -> bool {
+        self.training
+    }
+
+    /// Returns the learned weight matrix
+    pub fn get_weights(&self) -> &Tensor {
+        self.w.as_tensor()
+    }
+
+    /// Returns the moving average of the weights, once training has run with EMA enabled
+    pub fn get_ema_weights(&self) -> Option<&Tensor> {
+        self.ema_weights.as_ref()
+    }
+
+    /// Swaps the raw weights with their moving average
+    ///
+    /// Afterwards the forward pass, loss and generation use the averaged weights.
+    /// Calling it again restores the raw weights. Does nothing if no average has been
+    /// accumulated yet.
+    pub fn swap_ema_weights(&mut self) -> Result<(), MakemoreError> {
+        if let Some(ema) = self.ema_weights.take() {
+            let raw = self.w.as_tensor().copy()?;
+            self.w.set(&ema)?;
+            self.ema_weights = Some(raw);
+        }
+        Ok(())
+    }
+
+    /// Runs the forward pass, returning the raw pre-softmax logits
+    ///
+    /// Logits are what numerically stable cross-entropy and temperature scaling
+    /// operate on; [`NeuralBigramModel::forward`] turns them into probabilities.
+    ///
+    /// Multiplying a one-hot vector by `W` just picks out a row of `W`, so the logits are
+    /// computed by selecting rows with `index_select` instead of building one-hot vectors
+    /// and running a matmul. [`NeuralBigramModel::forward_logits_one_hot`] is the
+    /// equivalent matmul formulation.
+    ///
+    /// # Arguments
+    /// * `xs` - Tensor of input character indices
+    ///
+    /// # Returns
+    /// * Tensor of shape `[xs.len(), vocab_size]` holding the logits of each next character
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::{Device, Tensor};
+    /// use makemore_rs::apply_softmax;
+    /// use makemore_rs::neural::NeuralBigramModel;
+    ///
+    /// let device = Device::Cpu;
+    /// let model = NeuralBigramModel::new(27, &device)?;
+    /// let xs = Tensor::new(&[0i64, 5, 13], &device)?;
+    ///
+    /// let from_logits = apply_softmax(&model.forward_logits(&xs)?)?.to_vec2::<f32>()?;
+    /// let probs = model.forward(&xs)?.to_vec2::<f32>()?;
+    /// assert_eq!(from_logits, probs);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn forward_logits(&self, xs: &Tensor) -> Result<Tensor, MakemoreError> {
+        self.logits(xs, self.training)
+    }
+
+    /// Computes the logits by one-hot encoding the inputs and multiplying by the weights
+    ///
+    /// This is the textbook formulation of [`NeuralBigramModel::forward_logits`], at the
+    /// cost of materializing a `[xs.len(), vocab_size]` one-hot matrix. It never applies
+    /// dropout, so it gives the same result as `forward_logits` in evaluation mode or
+    /// when dropout is disabled.
+    ///
+    /// # Arguments
+    /// * `xs` - Tensor of input character indices
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::{Device, Tensor};
+    /// use makemore_rs::neural::NeuralBigramModel;
+    ///
+    /// let device = Device::Cpu;
+    /// let model = NeuralBigramModel::new(27, &device)?;
+    /// let xs = Tensor::new(&[0i64, 5, 13, 13, 1], &device)?;
+    ///
+    /// let selected = model.forward_logits(&xs)?.to_vec2::<f32>()?;
+    /// let one_hot = model.forward_logits_one_hot(&xs)?.to_vec2::<f32>()?;
+    /// for (a, b) in selected.iter().flatten().zip(one_hot.iter().flatten()) {
+    ///     assert!((a - b).abs() < 1e-6);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn forward_logits_one_hot(&self, xs: &Tensor) -> Result<Tensor, MakemoreError> {
+        // Convert input chars to one-hot vectors and compute logits for each next character
+        let xenc = create_one_hot_encoding(xs, self.vocab_size, &self.device)?;
+        Ok(xenc.matmul(self.w.as_tensor())?)
+    }
+
+    /// Runs the forward pass, returning next-character probabilities
+    ///
+    /// # Arguments
+    /// * `xs` - Tensor of input character indices
+    ///
+    /// # Returns
+    /// * Tensor of shape `[xs.len(), vocab_size]` where each row sums to 1
+    pub fn forward(&self, xs: &Tensor) -> Result<Tensor, MakemoreError> {
+        apply_softmax(&self.forward_logits(xs)?)
+    }
+
+    /// Computes the negative log likelihood of the targets plus L2 regularization
+    ///
+    /// The regularization term is `l2_strength * mean(W^2)`.
+    ///
+    /// # Arguments
+    /// * `xs` - Tensor of input character indices
+    /// * `ys` - Tensor of target character indices
+    ///
+    /// # Returns
+    /// * Scalar loss tensor
+    pub fn loss(&self, xs: &Tensor, ys: &Tensor) -> Result<Tensor, MakemoreError> {
+        let loss = self.nll(xs, ys, self.training)?;
+        if self.l2_strength == 0.0 {
+            return Ok(loss);
+        }
+
+        // Add L2 regularization like in the Python version
+        let l2_loss = self
+            .w
+            .powf(2.0)?
+            .mean_all()?
+            .affine(self.l2_strength, 0.0)?;
+        Ok(loss.add(&l2_loss)?)
+    }
+
+    /// Trains the model with stochastic gradient descent
+    ///
+    /// Each step runs the forward pass, computes the loss, backpropagates
+    /// gradients and updates the weights.
+    ///
+    /// # Arguments
+    /// * `xs` - Tensor of input character indices
+    /// * `ys` - Tensor of target character indices
+    /// * `steps` - Number of gradient descent steps
+    /// * `learning_rate` - Base SGD learning rate, adjusted by the model's [`LrSchedule`]
+    ///
+    /// # Returns
+    /// * Loss value at each step
+    pub fn train(
+        &mut self,
+        xs: &Tensor,
+        ys: &Tensor,
+        steps: usize,
+        learning_rate: f64,
+    ) -> Result<Vec<f32>, MakemoreError> {
+        let mut losses = Vec::with_capacity(steps);
+        self.train_with_callback(xs, ys, steps, learning_rate, |_, loss| {
+            losses.push(loss);
+            ControlFlow::Continue(())
+        })?;
+        Ok(losses)
+    }
+
+    /// Trains the model like [`NeuralBigramModel::train`], invoking a callback after every step
+    ///
+    /// The callback receives the step index and the loss computed at that step. Returning
+    /// `ControlFlow::Break` stops training before the next step, which allows custom
+    /// logging or convergence criteria.
+    ///
+    /// # Arguments
+    /// * `xs` - Tensor of input character indices
+    /// * `ys` - Tensor of target character indices
+    /// * `steps` - Maximum number of gradient descent steps
+    /// * `learning_rate` - Base SGD learning rate, adjusted by the model's [`LrSchedule`]
+    /// * `on_step` - Callback invoked with `(step, loss)` after each update
+    ///
+    /// # Returns
+    /// * Number of steps that were run
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::ops::ControlFlow;
+    /// use candle_core::{Device, Tensor};
+    /// use makemore_rs::neural::NeuralBigramModel;
+    ///
+    /// let device = Device::Cpu;
+    /// let xs = Tensor::new(&[0i64, 1], &device)?;
+    /// let ys = Tensor::new(&[1i64, 0], &device)?;
+    /// let mut model = NeuralBigramModel::new(27, &device)?;
+    ///
+    /// let mut seen = Vec::new();
+    /// let steps = model.train_with_callback(&xs, &ys, 10, 1.0, |step, _loss| {
+    ///     seen.push(step);
+    ///     if step == 2 {
+    ///         ControlFlow::Break(())
+    ///     } else {
+    ///         ControlFlow::Continue(())
+    ///     }
+    /// })?;
+    ///
+    /// assert_eq!(steps, 3);
+    /// assert_eq!(seen, vec![0, 1, 2]);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn train_with_callback(
+        &mut self,
+        xs: &Tensor,
+        ys: &Tensor,
+        steps: usize,
+        learning_rate: f64,
+        mut on_step: impl FnMut(usize, f32) -> ControlFlow<()>,
+    ) -> Result<usize, MakemoreError> {
+        let mut opt = SGD::new(vec![self.w.clone()], learning_rate)?;
+        self.init_ema()?;
+
+        for step in 0..steps {
+            let lr = self.lr_schedule.learning_rate(learning_rate, step, steps);
+            opt.set_learning_rate(lr);
+
+            let loss = self.loss(xs, ys)?;
+            opt.backward_step(&loss)?;
+            self.update_ema()?;
+
+            if on_step(step, loss.to_scalar::<f32>()?).is_break() {
+                return Ok(step + 1);
+            }
+        }
+
+        Ok(steps)
+    }
+
+    /// Trains the model until the validation loss stops improving
+    ///
+    /// After every epoch (one full-batch gradient step) the average negative log
+    /// likelihood of the validation set is evaluated, without dropout. Training stops once
+    /// it has not improved for `patience` consecutive epochs, and the weights from the
+    /// best epoch are restored.
+    ///
+    /// # Arguments
+    /// * `train_xs` - Tensor of training input character indices
+    /// * `train_ys` - Tensor of training target character indices
+    /// * `val_xs` - Tensor of validation input character indices
+    /// * `val_ys` - Tensor of validation target character indices
+    /// * `patience` - Number of epochs without improvement before stopping
+    /// * `max_epochs` - Upper bound on the number of epochs
+    /// * `learning_rate` - Base SGD learning rate, adjusted by the model's [`LrSchedule`]
+    ///
+    /// # Returns
+    /// * Number of epochs that were run
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::{Device, Tensor};
+    /// use makemore_rs::neural::NeuralBigramModel;
+    ///
+    /// let device = Device::Cpu;
+    /// let train_xs = Tensor::new(&[0i64, 1], &device)?;
+    /// let train_ys = Tensor::new(&[1i64, 0], &device)?;
+    /// // The validation transition never appears in training, so its loss cannot improve for long
+    /// let val_xs = Tensor::new(&[0i64], &device)?;
+    /// let val_ys = Tensor::new(&[2i64], &device)?;
+    ///
+    /// let mut model = NeuralBigramModel::new(27, &device)?;
+    /// let epochs =
+    ///     model.train_early_stop(&train_xs, &train_ys, &val_xs, &val_ys, 3, 100, 10.0)?;
+    /// assert!(epochs < 100);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub fn train_early_stop(
+        &mut self,
+        train_xs: &Tensor,
+        train_ys: &Tensor,
+        val_xs: &Tensor,
+        val_ys: &Tensor,
+        patience: usize,
+        max_epochs: usize,
+        learning_rate: f64,
+    ) -> Result<usize, MakemoreError> {
+        let mut opt = SGD::new(vec![self.w.clone()], learning_rate)?;
+        self.init_ema()?;
+        let mut best_loss = f32::INFINITY;
+        let mut best_weights = self.w.as_tensor().copy()?;
+        let mut epochs_without_improvement = 0;
+        let mut epochs = 0;
+
+        while epochs < max_epochs {
+            let lr = self
+                .lr_schedule
+                .learning_rate(learning_rate, epochs, max_epochs);
+            opt.set_learning_rate(lr);
+
+            let loss = self.loss(train_xs, train_ys)?;
+            opt.backward_step(&loss)?;
+            self.update_ema()?;
+            epochs += 1;
+
+            let val_loss = self.nll(val_xs, val_ys, false)?.to_scalar::<f32>()?;
+            debug!("Epoch {}: validation loss {}", epochs, val_loss);
+            if val_loss < best_loss {
+                best_loss = val_loss;
+                best_weights = self.w.as_tensor().copy()?;
+                epochs_without_improvement = 0;
+            } else {
+                epochs_without_improvement += 1;
+                if epochs_without_improvement >= patience {
+                    break;
+                }
+            }
+        }
+
+        self.w.set(&best_weights)?;
+        Ok(epochs)
+    }
+
+    /// Samples a name by repeatedly drawing the next character from the model
+    ///
+    /// Generation starts from the '.' token and stops when '.' is sampled again or
+    /// the name reaches the maximum generated length. The '.' token cannot be drawn on
+    /// the first step, so names are never empty. This decodes the output of
+    /// [`NeuralBigramModel::generate_indices`] at temperature 1.
+    ///
+    /// # Arguments
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * The generated name without boundary tokens
+    pub fn generate<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<String, MakemoreError> {
+        Ok(self
+            .generate_indices(rng, 1.0)?
+            .into_iter()
+            .filter(|&ix| ix != 0)
+            .map(index_to_char)
+            .collect())
+    }
+
+    /// Samples a name as a sequence of token indices
+    ///
+    /// Works like [`NeuralBigramModel::generate`] but leaves decoding to the caller, e.g.
+    /// to use a different vocabulary or to inspect the token ids. The logits are divided
+    /// by `temperature` before the softmax, so values below 1 sharpen the distribution and
+    /// values above 1 flatten it. Dropout is never applied, whatever the training mode.
+    ///
+    /// # Arguments
+    /// * `rng` - Random number generator used for sampling
+    /// * `temperature` - Positive, finite softmax temperature
+    ///
+    /// # Returns
+    /// * The sampled indices, ending with the '.' token (0) unless the name was cut off
+    ///   at the maximum generated length. The leading '.' is not included.
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidArgument`] if `temperature` is not positive and finite
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::index_to_char;
+    /// use makemore_rs::neural::NeuralBigramModel;
+    /// use rand::rngs::StdRng;
+    /// use rand::SeedableRng;
+    ///
+    /// let model = NeuralBigramModel::new(27, &Device::Cpu)?;
+    /// let indices = model.generate_indices(&mut StdRng::seed_from_u64(7), 1.0)?;
+    /// let name = model.generate(&mut StdRng::seed_from_u64(7))?;
+    /// assert_ne!(indices[0], 0);
+    ///
+    /// let decoded: String = indices
+    ///     .into_iter()
+    ///     .filter(|&ix| ix != 0)
+    ///     .map(index_to_char)
+    ///     .collect();
+    /// assert_eq!(decoded, name);
+    ///
+    /// let mut rng = StdRng::seed_from_u64(7);
+    /// for temperature in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+    ///     assert!(model.generate_indices(&mut rng, temperature).is_err());
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn generate_indices<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        temperature: f64,
+    ) -> Result<Vec<usize>, MakemoreError> {
+        if !(temperature > 0.0 && temperature.is_finite()) {
+            return Err(MakemoreError::InvalidArgument(format!(
+                "Temperature must be positive and finite, got {}",
+                temperature
+            )));
+        }
+
+        let mut indices = Vec::new();
+        let mut ix = 0;
+
+        while indices.len() < MAX_GENERATED_LEN {
+            let logits = self.logits(&Tensor::new(&[ix as i64], &self.device)?, false)?;
+            let probs = apply_softmax(&(logits / temperature)?)?;
+            let mut prob_vec: Vec<f32> = probs.squeeze(0)?.to_vec1()?;
+            mask_end_token(&mut prob_vec, indices.len(), 0);
+            ix = sample_from_probs(&prob_vec, rng)?;
+
+            indices.push(ix);
+            if ix == 0 {
+                break;
+            }
+        }
+
+        Ok(indices)
+    }
+
+    /// Finds the most probable names under the model using beam search
+    ///
+    /// Starting from the '.' token, every beam is expanded over all possible next
+    /// characters and only the `beam_width` highest scoring candidates are kept.
+    /// Candidates that produce the '.' end token are complete and leave the beam. The end
+    /// token is not considered on the first expansion, so no name is empty.
+    ///
+    /// # Arguments
+    /// * `vocab` - Vocabulary whose indices the model was trained on, used for decoding
+    /// * `beam_width` - Number of candidates kept at each step
+    /// * `max_len` - Maximum number of characters in a name
+    ///
+    /// # Returns
+    /// * Up to `beam_width` complete names with their log-probabilities, best first
+    ///
+    /// # Errors
+    /// * [`MakemoreError::ShapeMismatch`] if `vocab` does not have the same size as the model
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::{Device, Tensor};
+    /// use makemore_rs::neural::NeuralBigramModel;
+    /// use makemore_rs::vocabulary::Vocabulary;
+    ///
+    /// // '.' -> 'a' and 'a' -> '.' dominate every other transition
+    /// let mut w = vec![0f32; 27 * 27];
+    /// w[1] = 10.0;
+    /// w[27] = 10.0;
+    /// let w = Tensor::from_vec(w, (27, 27), &Device::Cpu)?;
+    /// let model = NeuralBigramModel::from_weights(&w)?;
+    ///
+    /// let vocab = Vocabulary::from_chars(&[
+    ///     ".", "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o",
+    ///     "p", "q", "r", "s", "t", "u", "v", "w", "x", "y", "z",
+    /// ])?;
+    /// let names = model.beam_search(&vocab, 3, 10)?;
+    /// assert_eq!(names[0].0, "a");
+    ///
+    /// // Any vocabulary size works, and '.' -> '.' never yields an empty name
+    /// let w = Tensor::new(&[[10f32, 0.0, 5.0], [0.0, 0.0, 0.0], [10.0, 0.0, 0.0]], &Device::Cpu)?;
+    /// let model = NeuralBigramModel::from_weights(&w)?;
+    /// let vocab = Vocabulary::from_chars(&[".", "x", "y"])?;
+    /// let names = model.beam_search(&vocab, 2, 10)?;
+    /// assert_eq!(names[0].0, "y");
+    /// assert!(names.iter().all(|(name, _)| !name.is_empty()));
+    /// assert!(model.beam_search(&Vocabulary::from_chars(&[".", "x"])?, 2, 10).is_err());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn beam_search(
+        &self,
+        vocab: &Vocabulary,
+        beam_width: usize,
+        max_len: usize,
+    ) -> Result<Vec<(String, f32)>, MakemoreError> {
+        if vocab.get_size() != self.vocab_size {
+            return Err(MakemoreError::ShapeMismatch {
+                expected: format!("a vocabulary of {} characters", self.vocab_size),
+                actual: format!("{} characters", vocab.get_size()),
+            });
+        }
+
+        // The one-hot forward pass selects row `i` of W, so the log-probabilities of every
+        // transition are the log-softmax of the weight rows
+        let log_probs = apply_softmax(self.w.as_tensor())?.log()?.to_vec2::<f32>()?;
+
+        let mut beams: Vec<(Vec<usize>, f32)> = vec![(Vec::new(), 0.0)];
+        let mut complete: Vec<(Vec<usize>, f32)> = Vec::new();
+
+        for _ in 0..=max_len {
+            let mut candidates: Vec<(Vec<usize>, f32)> = beams
+                .iter()
+                .flat_map(|(seq, score)| {
+                    let prev = seq.last().copied().unwrap_or(0);
+                    log_probs[prev]
+                        .iter()
+                        .enumerate()
+                        // Ending right after the start token would produce an empty name
+                        .filter(move |&(ix, _)| ix != 0 || !seq.is_empty())
+                        .map(move |(ix, lp)| {
+                            let mut next = seq.clone();
+                            next.push(ix);
+                            (next, score + lp)
+                        })
+                })
+                .collect();
+            candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+            candidates.truncate(beam_width);
+
+            beams = Vec::new();
+            for (seq, score) in candidates {
+                if seq.last() == Some(&0) {
+                    complete.push((seq, score));
+                } else if seq.len() <= max_len {
+                    beams.push((seq, score));
+                }
+            }
+
+            if beams.is_empty() {
+                break;
+            }
+        }
+
+        complete.sort_by(|a, b| b.1.total_cmp(&a.1));
+        complete.truncate(beam_width);
+        Ok(complete
+            .into_iter()
+            .map(|(seq, score)| (vocab.decode(&seq), score))
+            .collect())
+    }
+
+    /// Returns the learned transition probabilities keyed by character pair
+    ///
+    /// Each weight row is softmaxed into the distribution of the character following
+    /// that row's character. The map has the same format as
+    /// [`crate::bigrams::BigramModel::get_probabilities_map`], so the learned
+    /// distribution can be compared with or plotted like the count-based one.
+    ///
+    /// # Arguments
+    /// * `vocab` - Vocabulary whose indices the model was trained on
+    ///
+    /// # Errors
+    /// * [`MakemoreError::ShapeMismatch`] if `vocab` does not have the same size as the model
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::{Device, Tensor};
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::neural::NeuralBigramModel;
+    /// use makemore_rs::vocabulary::Vocabulary;
+    ///
+    /// let device = Device::Cpu;
+    /// let vocab = Vocabulary::new(&[NameItem::new("abcdefghijklmnopqrstuvwxyz")]);
+    /// let xs = Tensor::new(&[0i64, 1, 2], &device)?;
+    /// let ys = Tensor::new(&[1i64, 2, 0], &device)?;
+    /// let mut model = NeuralBigramModel::new(27, &device)?;
+    /// model.train(&xs, &ys, 20, 10.0)?;
+    ///
+    /// let probs = model.to_probability_map(&vocab)?;
+    /// assert_eq!(probs.len(), 27 * 27);
+    /// for a in vocab.get_chars() {
+    ///     let row_sum: f32 = vocab.get_chars().iter().map(|b| probs[&(a.clone(), b.clone())]).sum();
+    ///     assert!((row_sum - 1.0).abs() < 1e-5);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn to_probability_map(
+        &self,
+        vocab: &Vocabulary,
+    ) -> Result<HashMap<(String, String), f32>, MakemoreError> {
+        if vocab.get_size() != self.vocab_size {
+            return Err(MakemoreError::ShapeMismatch {
+                expected: format!("a vocabulary of {} characters", self.vocab_size),
+                actual: format!("{} characters", vocab.get_size()),
+            });
+        }
+
+        let chars = vocab.get_chars();
+        let probs = apply_softmax(self.w.as_tensor())?.to_vec2::<f32>()?;
+        Ok(probs
+            .iter()
+            .enumerate()
+            .flat_map(|(i, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(move |(j, &p)| ((chars[i].clone(), chars[j].clone()), p))
+            })
+            .collect())
+    }
+
+    /// Selects the logits of `xs`, applying dropout only if `training` is set
+    fn logits(&self, xs: &Tensor, training: bool) -> Result<Tensor, MakemoreError> {
+        // Row i of W holds the logits of every character following character i
+        let logits = self.w.as_tensor().index_select(xs, 0)?;
+        dropout(&logits, self.dropout, training)
+    }
+
+    /// Average negative log likelihood of the targets, without regularization
+    ///
+    /// Computed from the logits with a log-softmax based cross-entropy, which avoids
+    /// taking the log of probabilities that underflow to zero. Dropout is applied only if
+    /// `training` is set.
+    fn nll(&self, xs: &Tensor, ys: &Tensor, training: bool) -> Result<Tensor, MakemoreError> {
+        let logits = self.logits(xs, training)?;
+        Ok(candle_nn::loss::cross_entropy(&logits, ys)?)
+    }
+
+    /// Starts the moving average from the current weights if EMA is enabled and no
+    /// average exists yet
+    fn init_ema(&mut self) -> Result<(), MakemoreError> {
+        if self.ema_decay.is_some() && self.ema_weights.is_none() {
+            self.ema_weights = Some(self.w.as_tensor().copy()?);
+        }
+        Ok(())
+    }
+
+    /// Moves the weight average towards the current weights by one step
+    fn update_ema(&mut self) -> Result<(), MakemoreError> {
+        if let (Some(decay), Some(ema)) = (self.ema_decay, &self.ema_weights) {
+            let updated = ema
+                .affine(decay, 0.0)?
+                .add(&self.w.affine(1.0 - decay, 0.0)?)?;
+            self.ema_weights = Some(updated);
+        }
+        Ok(())
+    }
+}
+
+/// Creates weights drawn uniformly from `[-bound, bound]` with `bound = sqrt(6 / fan_in)`
+///
+/// This is Kaiming (He) initialization for layers followed by a ReLU: the weights have
+/// standard deviation `sqrt(2 / fan_in)`, which keeps the variance of activations
+/// roughly constant from layer to layer instead of the blow-up or decay that unit
+/// variance weights cause in deeper networks.
+///
+/// # Arguments
+/// * `shape` - Shape of the weight tensor
+/// * `fan_in` - Number of inputs feeding each output unit
+/// * `device` - Device to store tensors on (CPU/GPU)
+///
+/// # Examples
+///
+/// ```
+/// use candle_core::Device;
+/// use makemore_rs::neural::kaiming_uniform;
+///
+/// let w = kaiming_uniform((200, 300), 200, &Device::Cpu)?;
+/// let std = w.as_tensor().sqr()?.mean_all()?.sqrt()?.to_scalar::<f32>()?;
+/// let expected = (2.0f32 / 200.0).sqrt();
+/// assert!((std - expected).abs() < 0.05 * expected);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn kaiming_uniform<S: Into<Shape>>(
+    shape: S,
+    fan_in: usize,
+    device: &Device,
+) -> Result<Var, MakemoreError> {
+    let bound = (6.0 / fan_in as f32).sqrt();
+    Ok(Var::rand(-bound, bound, shape, device)?)
+}
+
+/// Creates weights drawn from a normal distribution with standard deviation
+/// `sqrt(2 / (fan_in + fan_out))`
+///
+/// This is Xavier (Glorot) initialization, which balances the variance of activations in
+/// the forward pass and of gradients in the backward pass for layers followed by a
+/// symmetric activation such as tanh.
+///
+/// # Arguments
+/// * `shape` - Shape of the weight tensor
+/// * `fan_in` - Number of inputs feeding each output unit
+/// * `fan_out` - Number of outputs each input unit feeds
+/// * `device` - Device to store tensors on (CPU/GPU)
+///
+/// # Examples
+///
+/// ```
+/// use candle_core::Device;
+/// use makemore_rs::neural::xavier_normal;
+///
+/// let w = xavier_normal((200, 300), 200, 300, &Device::Cpu)?;
+/// let std = w.as_tensor().sqr()?.mean_all()?.sqrt()?.to_scalar::<f32>()?;
+/// let expected = (2.0f32 / 500.0).sqrt();
+/// assert!((std - expected).abs() < 0.05 * expected);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn xavier_normal<S: Into<Shape>>(
+    shape: S,
+    fan_in: usize,
+    fan_out: usize,
+    device: &Device,
+) -> Result<Var, MakemoreError> {
+    let std = (2.0 / (fan_in + fan_out) as f32).sqrt();
+    Ok(Var::randn(0f32, std, shape, device)?)
+}
+
+/// Applies inverted dropout to `xs`
+///
+/// In training mode every element is zeroed with probability `p` and the survivors are
+/// scaled by `1 / (1 - p)`, so the expected value of each element is unchanged and
+/// nothing needs rescaling at inference. Outside training mode, or with `p == 0`, `xs`
+/// is returned unchanged.
+///
+/// # Arguments
+/// * `xs` - Input tensor
+/// * `p` - Probability of zeroing each element, in `[0, 1)`
+/// * `training` - Whether to drop elements
+///
+/// # Errors
+/// * [`MakemoreError::InvalidArgument`] if `p` is outside `[0, 1)`
+///
+/// # Examples
+///
+/// ```
+/// use candle_core::{DType, Device, Tensor};
+/// use makemore_rs::neural::dropout;
+///
+/// let xs = Tensor::ones(100_000, DType::F32, &Device::Cpu)?;
+///
+/// assert_eq!(dropout(&xs, 0.0, true)?.to_vec1::<f32>()?, xs.to_vec1::<f32>()?);
+/// assert_eq!(dropout(&xs, 0.5, false)?.to_vec1::<f32>()?, xs.to_vec1::<f32>()?);
+///
+/// // Half the elements become 0 and the rest 2, keeping the mean at 1
+/// let dropped = dropout(&xs, 0.5, true)?;
+/// assert!(dropped.to_vec1::<f32>()?.iter().all(|&x| x == 0.0 || x == 2.0));
+/// let mean = dropped.mean_all()?.to_scalar::<f32>()?;
+/// assert!((mean - 1.0).abs() < 0.02);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn dropout(xs: &Tensor, p: f64, training: bool) -> Result<Tensor, MakemoreError> {
+    if !(0.0..1.0).contains(&p) {
+        return Err(MakemoreError::InvalidArgument(format!(
+            "Dropout probability must be in [0, 1), got {}",
+            p
+        )));
+    }
+    if !training || p == 0.0 {
+        return Ok(xs.clone());
+    }
+
+    let keep = Tensor::rand(0f32, 1f32, xs.shape(), xs.device())?
+        .ge(p as f32)?
+        .to_dtype(xs.dtype())?;
+    Ok(xs.mul(&keep)?.affine(1.0 / (1.0 - p), 0.0)?)
+}
+
+/// Checks the model's backpropagated gradients against finite differences
+///
+/// Like [`crate::verify_matrix_multiplication`] this is a teaching aid: it confirms that
+/// what autodiff computes is really the derivative of the loss. The analytic gradient
+/// of every weight is compared with the central difference
+/// `(loss(w + epsilon) - loss(w - epsilon)) / (2 * epsilon)`, and the largest relative
+/// error `|analytic - numeric| / (|analytic| + |numeric|)` is returned. Both are
+/// computed on a double precision copy of the weights, so round-off does not swamp the
+/// comparison. Every weight costs two loss evaluations, so use a small model.
+///
+/// # Arguments
+/// * `model` - Model whose loss, including L2 regularization, is differentiated
+/// * `xs` - Tensor of input character indices
+/// * `ys` - Tensor of target character indices
+/// * `epsilon` - Step used for the finite differences
+///
+/// # Returns
+/// * The maximum relative error over all weights
+///
+/// # Examples
+///
+/// ```
+/// use candle_core::{Device, Tensor};
+/// use makemore_rs::neural::{grad_check, NeuralBigramModel};
+///
+/// let device = Device::Cpu;
+/// let w = Tensor::randn(0f32, 1f32, (5, 5), &device)?;
+/// let model = NeuralBigramModel::from_weights(&w)?;
+/// let xs = Tensor::new(&[0i64, 1, 2, 3, 1], &device)?;
+/// let ys = Tensor::new(&[1i64, 2, 3, 0, 4], &device)?;
+///
+/// assert!(grad_check(&model, &xs, &ys, 1e-4)? < 1e-5);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn grad_check(
+    model: &NeuralBigramModel,
+    xs: &Tensor,
+    ys: &Tensor,
+    epsilon: f64,
+) -> Result<f32, MakemoreError> {
+    let w = model.get_weights().to_dtype(DType::F64)?;
+    let with_weights = |w: &Tensor| -> Result<NeuralBigramModel, MakemoreError> {
+        Ok(NeuralBigramModel::from_weights(w)?.with_l2_strength(model.l2_strength))
+    };
+
+    let analytic_model = with_weights(&w)?;
+    let grads = analytic_model.loss(xs, ys)?.backward()?;
+    let analytic = grads
+        .get(analytic_model.get_weights())
+        .ok_or_else(|| {
+            candle_core::Error::Msg("Loss has no gradient with respect to the weights".into())
+        })?
+        .flatten_all()?
+        .to_vec1::<f64>()?;
+
+    let mut values = w.flatten_all()?.to_vec1::<f64>()?;
+    let loss_at = |values: &[f64]| -> Result<f64, MakemoreError> {
+        let w = Tensor::from_slice(values, w.shape(), w.device())?;
+        Ok(with_weights(&w)?.loss(xs, ys)?.to_scalar::<f64>()?)
+    };
+
+    let mut max_error = 0f64;
+    for (ix, &grad) in analytic.iter().enumerate() {
+        let original = values[ix];
+        values[ix] = original + epsilon;
+        let plus = loss_at(&values)?;
+        values[ix] = original - epsilon;
+        let minus = loss_at(&values)?;
+        values[ix] = original;
+
+        let numeric = (plus - minus) / (2.0 * epsilon);
+        let scale = (grad.abs() + numeric.abs()).max(f64::MIN_POSITIVE);
+        max_error = max_error.max((grad - numeric).abs() / scale);
+    }
+    debug!("Gradient check max relative error: {}", max_error);
+
+    Ok(max_error as f32)
+}
@@ -1,10 +1,200 @@
-use anyhow::Result;
+use crate::vocabulary::Vocabulary;
+use crate::MakemoreError;
 use plotters::{
+    coord::Shift,
     prelude::*,
     style::text_anchor::{HPos, Pos, VPos},
 };
 use std::collections::HashMap;
 
+/// Rendering options for the bigram heatmaps, passed to [`plot_bigram_heatmap_with`].
+///
+/// Start from [`PlotConfig::default`] (or [`PlotConfig::new`]) and override only what is
+/// needed with the `with_*` methods. The defaults write a 1200x1000 heatmap without
+/// marginals to `heatmap.png`, with a 30px title, 15px axis labels and 10px cell text.
+///
+/// # Examples
+/// ```
+/// use makemore_rs::plot::PlotConfig;
+///
+/// let config = PlotConfig::new()
+///     .with_output_path("counts.png")
+///     .with_title("Bigram Counts")
+///     .with_resolution(2400, 2000)
+///     .with_marginals(true);
+/// assert_eq!(config.get_output_path(), "counts.png");
+/// assert_eq!(config.get_title(), "Bigram Counts");
+/// assert_eq!(config.get_resolution(), (2400, 2000));
+/// assert!(config.get_marginals());
+/// assert_eq!(PlotConfig::default().get_resolution(), (1200, 1000));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlotConfig {
+    /// Path where the output image will be saved
+    output_path: String,
+    /// Title displayed above the heatmap
+    title: String,
+    /// Canvas width in pixels
+    width: u32,
+    /// Canvas height in pixels
+    height: u32,
+    /// Whether to draw row and column sums alongside the grid
+    marginals: bool,
+    /// Font size of the title in pixels
+    caption_font_size: u32,
+    /// Font size of the axis labels in pixels
+    label_font_size: u32,
+    /// Font size of the bigram and value text inside each cell in pixels
+    cell_font_size: u32,
+    /// Whether to leave out axis labels of characters without any non-zero bigram
+    skip_empty_labels: bool,
+}
+
+impl Default for PlotConfig {
+    fn default() -> Self {
+        Self {
+            output_path: "heatmap.png".to_string(),
+            title: "Bigram Heatmap".to_string(),
+            width: 1200,
+            height: 1000,
+            marginals: false,
+            caption_font_size: 30,
+            label_font_size: 15,
+            cell_font_size: 10,
+            skip_empty_labels: false,
+        }
+    }
+}
+
+impl PlotConfig {
+    /// Creates a config with the default settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the path where the output image will be saved
+    pub fn with_output_path(mut self, output_path: impl Into<String>) -> Self {
+        self.output_path = output_path.into();
+        self
+    }
+
+    /// Sets the title displayed above the heatmap
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Sets the canvas size in pixels.
+    ///
+    /// Bitmaps carry no DPI information, so for print-quality figures scale the
+    /// resolution up instead.
+    pub fn with_resolution(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Sets whether to draw the row sums to the right of the grid and the column sums
+    /// above it.
+    ///
+    /// For count data these are how often each character appears as the first and
+    /// second element of a bigram respectively.
+    pub fn with_marginals(mut self, marginals: bool) -> Self {
+        self.marginals = marginals;
+        self
+    }
+
+    /// Sets the font size of the title in pixels
+    pub fn with_caption_font_size(mut self, size: u32) -> Self {
+        self.caption_font_size = size;
+        self
+    }
+
+    /// Sets the font size of the character labels along both axes in pixels
+    pub fn with_label_font_size(mut self, size: u32) -> Self {
+        self.label_font_size = size;
+        self
+    }
+
+    /// Sets the font size of the bigram and value text drawn inside each cell in pixels.
+    ///
+    /// Scale this with the resolution, as the cells grow with the canvas.
+    ///
+    /// # Examples
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::plot::{plot_bigram_heatmap_with, PlotConfig};
+    ///
+    /// let model = BigramModel::from_words(&["emma", "ava"], &Device::Cpu)?;
+    /// let path = std::env::temp_dir().join("bigram_fonts.png");
+    /// let config = PlotConfig::new()
+    ///     .with_output_path(path.to_str().unwrap())
+    ///     .with_resolution(2400, 2000)
+    ///     .with_caption_font_size(60)
+    ///     .with_label_font_size(30)
+    ///     .with_cell_font_size(20);
+    /// assert_eq!(config.get_cell_font_size(), 20);
+    ///
+    /// plot_bigram_heatmap_with(
+    ///     model.get_counts(),
+    ///     model.get_chars(),
+    ///     model.get_vocabulary().get_char_to_idx(),
+    ///     &config,
+    /// )?;
+    /// assert!(std::fs::metadata(&path)?.len() > 0);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn with_cell_font_size(mut self, size: u32) -> Self {
+        self.cell_font_size = size;
+        self
+    }
+
+    /// Sets whether to label only characters that take part in at least one non-zero
+    /// bigram, as either the first or the second character.
+    ///
+    /// This thins out the axes of large, sparse vocabularies. The grid itself keeps a row
+    /// and column for every character; the rows and columns of unused characters are just
+    /// empty and unlabeled. See [`heatmap_labels`] for the labels that are drawn.
+    pub fn with_skip_empty_labels(mut self, skip_empty_labels: bool) -> Self {
+        self.skip_empty_labels = skip_empty_labels;
+        self
+    }
+
+    pub fn get_output_path(&self) -> &str {
+        &self.output_path
+    }
+
+    pub fn get_title(&self) -> &str {
+        &self.title
+    }
+
+    /// Returns the canvas size as `(width, height)` in pixels
+    pub fn get_resolution(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn get_marginals(&self) -> bool {
+        self.marginals
+    }
+
+    pub fn get_caption_font_size(&self) -> u32 {
+        self.caption_font_size
+    }
+
+    pub fn get_label_font_size(&self) -> u32 {
+        self.label_font_size
+    }
+
+    pub fn get_cell_font_size(&self) -> u32 {
+        self.cell_font_size
+    }
+
+    pub fn get_skip_empty_labels(&self) -> bool {
+        self.skip_empty_labels
+    }
+}
+
 /// Creates a heatmap visualization of bigram data, showing the relationships between character pairs.
 ///
 /// This function generates a detailed heatmap visualization where each cell represents a bigram (pair of characters)
@@ -49,7 +239,7 @@ use std::collections::HashMap;
 /// ```
 ///
 /// # Implementation Details
-/// - Uses a 1200x1000 pixel bitmap canvas
+/// - Uses a 1200x1000 pixel bitmap canvas; see [`plot_bigram_heatmap_with`] to change it
 /// - Draws a grid where each cell represents a possible character pair
 /// - For non-zero values:
 ///   - Colors the cell with red intensity based on the value
@@ -63,24 +253,201 @@ pub fn plot_bigram_heatmap<T: Into<f64> + Copy>(
     char_to_idx: &HashMap<String, usize>,
     output_path: &str,
     title: &str,
-) -> Result<()> {
+) -> Result<(), MakemoreError> {
+    let config = PlotConfig::new()
+        .with_output_path(output_path)
+        .with_title(title);
+    plot_bigram_heatmap_with(b, chars, char_to_idx, &config)
+}
+
+/// Creates a bigram heatmap with marginal frequency bars.
+///
+/// Works like [`plot_bigram_heatmap`], but additionally draws the column sums as a bar
+/// strip above the grid and the row sums as a bar strip to its right. This is shorthand
+/// for [`plot_bigram_heatmap_with`] and [`PlotConfig::with_marginals`].
+///
+/// # Arguments
+/// * `b` - HashMap containing bigram pairs as keys (tuple of strings) and their corresponding values
+/// * `chars` - Vector of strings representing the character vocabulary
+/// * `char_to_idx` - HashMap mapping characters to their indices in the vocabulary
+/// * `output_path` - Path where the output image will be saved
+/// * `title` - Title to be displayed on the heatmap
+///
+/// # Returns
+/// * `Result<()>` - Ok(()) if the heatmap was successfully generated and saved, Error otherwise
+///
+/// # Example
+/// ```
+/// use candle_core::Device;
+/// use makemore_rs::bigrams::BigramModel;
+/// use makemore_rs::plot::plot_bigram_heatmap_with_marginals;
+///
+/// let model = BigramModel::from_words(&["emma", "ava", "anna"], &Device::Cpu)?;
+/// let path = std::env::temp_dir().join("bigram_marginals.png");
+/// plot_bigram_heatmap_with_marginals(
+///     model.get_counts(),
+///     model.get_chars(),
+///     model.get_vocabulary().get_char_to_idx(),
+///     path.to_str().unwrap(),
+///     "Bigram Counts",
+/// )?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn plot_bigram_heatmap_with_marginals<T: Into<f64> + Copy>(
+    b: &HashMap<(String, String), T>,
+    chars: &[String],
+    char_to_idx: &HashMap<String, usize>,
+    output_path: &str,
+    title: &str,
+) -> Result<(), MakemoreError> {
+    let config = PlotConfig::new()
+        .with_output_path(output_path)
+        .with_title(title)
+        .with_marginals(true);
+    plot_bigram_heatmap_with(b, chars, char_to_idx, &config)
+}
+
+/// Returns the axis labels [`plot_bigram_heatmap_with`] draws for each character.
+///
+/// Labels are the characters themselves, in vocabulary order. With
+/// [`PlotConfig::with_skip_empty_labels`] the label of a character whose row and column
+/// are both all zero is left empty.
+///
+/// # Arguments
+/// * `data` - HashMap containing bigram pairs as keys (tuple of strings) and their corresponding values
+/// * `chars` - Vector of strings representing the character vocabulary
+/// * `char_to_idx` - HashMap mapping characters to their indices in the vocabulary
+/// * `config` - Rendering options
+///
+/// # Example
+/// ```
+/// use candle_core::Device;
+/// use makemore_rs::bigrams::BigramModel;
+/// use makemore_rs::plot::{heatmap_labels, PlotConfig};
+/// use makemore_rs::vocabulary::Vocabulary;
+///
+/// let vocab = Vocabulary::from_chars(&[".", "a", "b", "c", "d"])?;
+/// let names = vec![makemore_rs::data::NameItem::new("ab")];
+/// let model = BigramModel::new_with_vocabulary(&names, vocab, &Device::Cpu)?;
+/// let char_to_idx = model.get_vocabulary().get_char_to_idx();
+///
+/// let config = PlotConfig::new().with_skip_empty_labels(true);
+/// let labels = heatmap_labels(model.get_counts(), model.get_chars(), char_to_idx, &config);
+/// assert_eq!(labels, vec![".", "a", "b", "", ""]);
+///
+/// let labels = heatmap_labels(model.get_counts(), model.get_chars(), char_to_idx, &PlotConfig::new());
+/// assert_eq!(labels, vec![".", "a", "b", "c", "d"]);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn heatmap_labels<T: Into<f64> + Copy>(
+    data: &HashMap<(String, String), T>,
+    chars: &[String],
+    char_to_idx: &HashMap<String, usize>,
+    config: &PlotConfig,
+) -> Vec<String> {
+    if !config.get_skip_empty_labels() {
+        return chars.to_vec();
+    }
+
+    let data = bigram_matrix(data, chars.len(), char_to_idx);
+    chars
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let used = data[i].iter().any(|&v| v != 0.0) || data.iter().any(|row| row[i] != 0.0);
+            if used {
+                c.clone()
+            } else {
+                String::new()
+            }
+        })
+        .collect()
+}
+
+/// Size in pixels of the marginal bar strips
+const MARGINAL_SIZE: u32 = 150;
+
+/// Creates a bigram heatmap using the rendering options in `config`.
+///
+/// This is the general form of [`plot_bigram_heatmap`]; the output path, title,
+/// resolution, marginal bars and font sizes all come from the [`PlotConfig`].
+///
+/// # Arguments
+/// * `data` - HashMap containing bigram pairs as keys (tuple of strings) and their corresponding values
+/// * `chars` - Vector of strings representing the character vocabulary
+/// * `char_to_idx` - HashMap mapping characters to their indices in the vocabulary
+/// * `config` - Rendering options
+///
+/// # Returns
+/// * `Result<()>` - Ok(()) if the heatmap was successfully generated and saved, Error otherwise
+///
+/// # Example
+/// ```
+/// use candle_core::Device;
+/// use makemore_rs::bigrams::BigramModel;
+/// use makemore_rs::plot::{plot_bigram_heatmap_with, PlotConfig};
+///
+/// let model = BigramModel::from_words(&["emma", "ava"], &Device::Cpu)?;
+/// let path = std::env::temp_dir().join("bigram_config.png");
+/// let config = PlotConfig::new()
+///     .with_output_path(path.to_str().unwrap())
+///     .with_title("Bigram Counts")
+///     .with_resolution(640, 480);
+/// plot_bigram_heatmap_with(
+///     model.get_counts(),
+///     model.get_chars(),
+///     model.get_vocabulary().get_char_to_idx(),
+///     &config,
+/// )?;
+///
+/// // The PNG IHDR chunk stores the width and height as big-endian u32s at offset 16
+/// let png = std::fs::read(&path)?;
+/// assert_eq!(u32::from_be_bytes(png[16..20].try_into()?), 640);
+/// assert_eq!(u32::from_be_bytes(png[20..24].try_into()?), 480);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn plot_bigram_heatmap_with<T: Into<f64> + Copy>(
+    data: &HashMap<(String, String), T>,
+    chars: &[String],
+    char_to_idx: &HashMap<String, usize>,
+    config: &PlotConfig,
+) -> Result<(), MakemoreError> {
+    let output_path = config.get_output_path();
+    let title = config.get_title();
+    let show_marginals = config.get_marginals();
+    let caption_font = ("sans-serif", config.get_caption_font_size());
+    let label_font = ("sans-serif", config.get_label_font_size());
+    let cell_font_size = config.get_cell_font_size();
     let n = chars.len();
+    let labels = heatmap_labels(data, chars, char_to_idx, config);
 
     // Create the heatmap data
-    let mut data = vec![vec![0.0; n]; n];
-    for ((ch1, ch2), count) in b {
-        let i = char_to_idx[ch1];
-        let j = char_to_idx[ch2];
-        data[i][j] = (*count).into();
-    }
+    let data = bigram_matrix(data, n, char_to_idx);
 
-    let root = BitMapBackend::new(output_path, (1200, 1000)).into_drawing_area();
+    let root = BitMapBackend::new(output_path, config.get_resolution()).into_drawing_area();
     root.fill(&WHITE)?;
 
+    let heatmap_area = if show_marginals {
+        // The title goes above the column bars instead of between them and the grid
+        let titled = root.titled(title, caption_font)?;
+        let (width, _) = titled.dim_in_pixel();
+        let (top, bottom) = titled.split_vertically(MARGINAL_SIZE);
+        let (column_area, _) = top.split_horizontally(width.saturating_sub(MARGINAL_SIZE));
+        let (heatmap_area, row_area) =
+            bottom.split_horizontally(width.saturating_sub(MARGINAL_SIZE));
+        draw_marginals(&column_area, &row_area, &data)?;
+        heatmap_area
+    } else {
+        root.clone()
+    };
+
     let max_val = data.iter().flatten().fold(0.0_f64, |a, &b| a.max(b));
 
-    let mut chart = ChartBuilder::on(&root)
-        .caption(title, ("sans-serif", 30))
+    let mut builder = ChartBuilder::on(&heatmap_area);
+    if !show_marginals {
+        builder.caption(title, caption_font);
+    }
+    let mut chart = builder
         .margin(60)
         .x_label_area_size(60)
         .y_label_area_size(60)
@@ -92,10 +459,10 @@ pub fn plot_bigram_heatmap<T: Into<f64> + Copy>(
         .disable_y_mesh()
         .x_labels(n)
         .y_labels(n)
-        .x_label_style(("sans-serif", 15))
-        .y_label_style(("sans-serif", 15))
-        .x_label_formatter(&|x| chars[x.round() as usize].clone())
-        .y_label_formatter(&|y| chars[y.round() as usize].clone())
+        .x_label_style(label_font)
+        .y_label_style(label_font)
+        .x_label_formatter(&|x| labels[x.round() as usize].clone())
+        .y_label_formatter(&|y| labels[y.round() as usize].clone())
         .draw()?;
 
     let plotting_area = chart.plotting_area();
@@ -119,7 +486,7 @@ pub fn plot_bigram_heatmap<T: Into<f64> + Copy>(
                 plotting_area.draw(&Text::new(
                     format!("{}{}", chars[i], chars[j]),
                     (j as f32, i as f32 - 0.2),
-                    ("sans-serif", 10)
+                    ("sans-serif", cell_font_size)
                         .into_font()
                         .color(&BLACK)
                         .pos(Pos::new(HPos::Center, VPos::Center)),
@@ -132,7 +499,7 @@ pub fn plot_bigram_heatmap<T: Into<f64> + Copy>(
                         format!("{:.3}", value)
                     },
                     (j as f32, i as f32 + 0.2),
-                    ("sans-serif", 10)
+                    ("sans-serif", cell_font_size)
                         .into_font()
                         .color(&BLACK)
                         .pos(Pos::new(HPos::Center, VPos::Center)),
@@ -145,3 +512,330 @@ pub fn plot_bigram_heatmap<T: Into<f64> + Copy>(
     println!("Heatmap saved as {}", output_path);
     Ok(())
 }
+
+/// Creates a heatmap of the element-wise difference `a - b` between two bigram maps.
+///
+/// Uses a diverging color scale: cells where `a` is larger are red, cells where `b` is
+/// larger are blue, and cells with no difference stay white. Color intensity is scaled by
+/// the largest absolute difference. This is useful for comparing two models, e.g. before
+/// and after smoothing.
+///
+/// # Arguments
+/// * `a` - HashMap containing the first set of bigram values
+/// * `b` - HashMap containing the second set of bigram values
+/// * `chars` - Vector of strings representing the character vocabulary
+/// * `char_to_idx` - HashMap mapping characters to their indices in the vocabulary
+/// * `output_path` - Path where the output image will be saved
+/// * `title` - Title to be displayed on the heatmap
+///
+/// # Returns
+/// * `Result<()>` - Ok(()) if the heatmap was successfully generated and saved, Error otherwise
+///
+/// # Example
+/// ```
+/// use candle_core::Device;
+/// use makemore_rs::bigrams::BigramModel;
+/// use makemore_rs::data::NameItem;
+/// use makemore_rs::plot::plot_bigram_diff;
+///
+/// let names = vec![NameItem::new("emma"), NameItem::new("ava")];
+/// let model = BigramModel::new(&names, &Device::Cpu)?;
+/// let probs = model.get_probabilities_map().unwrap();
+///
+/// // A model compared against itself renders an all-white grid
+/// let path = std::env::temp_dir().join("bigram_diff.png");
+/// let path = path.to_str().unwrap();
+/// plot_bigram_diff(
+///     &probs,
+///     &probs,
+///     model.get_chars(),
+///     model.get_vocabulary().get_char_to_idx(),
+///     path,
+///     "Self Difference",
+/// )?;
+/// assert!(std::fs::metadata(path)?.len() > 0);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn plot_bigram_diff<T: Into<f64> + Copy>(
+    a: &HashMap<(String, String), T>,
+    b: &HashMap<(String, String), T>,
+    chars: &[String],
+    char_to_idx: &HashMap<String, usize>,
+    output_path: &str,
+    title: &str,
+) -> Result<(), MakemoreError> {
+    let n = chars.len();
+    let diff = bigram_diff_matrix(a, b, n, char_to_idx);
+
+    let root = BitMapBackend::new(output_path, (1200, 1000)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let max_abs = diff.iter().flatten().fold(0.0_f64, |m, &v| m.max(v.abs()));
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 30))
+        .margin(60)
+        .x_label_area_size(60)
+        .y_label_area_size(60)
+        .build_cartesian_2d(-0.5f32..(n as f32 - 0.5), (n as f32 - 0.5)..(-0.5f32))?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .disable_y_mesh()
+        .x_labels(n)
+        .y_labels(n)
+        .x_label_style(("sans-serif", 15))
+        .y_label_style(("sans-serif", 15))
+        .x_label_formatter(&|x| chars[x.round() as usize].clone())
+        .y_label_formatter(&|y| chars[y.round() as usize].clone())
+        .draw()?;
+
+    let plotting_area = chart.plotting_area();
+    for i in 0..n {
+        for j in 0..n {
+            let value = diff[i][j];
+            if value == 0.0 || max_abs == 0.0 {
+                continue;
+            }
+
+            // Fade from white towards red (positive) or blue (negative)
+            let fade = ((1.0 - value.abs() / max_abs) * 255.0) as u8;
+            let color = if value > 0.0 {
+                RGBColor(255, fade, fade)
+            } else {
+                RGBColor(fade, fade, 255)
+            };
+            plotting_area.draw(&Rectangle::new(
+                [
+                    (j as f32 - 0.5, i as f32 - 0.5),
+                    (j as f32 + 0.5, i as f32 + 0.5),
+                ],
+                color.filled(),
+            ))?;
+
+            plotting_area.draw(&Text::new(
+                format!("{}{}", chars[i], chars[j]),
+                (j as f32, i as f32 - 0.2),
+                ("sans-serif", 10)
+                    .into_font()
+                    .color(&BLACK)
+                    .pos(Pos::new(HPos::Center, VPos::Center)),
+            ))?;
+
+            plotting_area.draw(&Text::new(
+                format!("{:+.3}", value),
+                (j as f32, i as f32 + 0.2),
+                ("sans-serif", 10)
+                    .into_font()
+                    .color(&BLACK)
+                    .pos(Pos::new(HPos::Center, VPos::Center)),
+            ))?;
+        }
+    }
+
+    root.present()?;
+    println!("Difference heatmap saved as {}", output_path);
+    Ok(())
+}
+
+/// Creates a bar chart of the probability of each transition in a generated name.
+///
+/// Each bar corresponds to one transition of the name wrapped in '.' boundary tokens, as
+/// returned by `BigramModel::sample_name_with_trace`, and is labelled with its token
+/// pair. Low bars show the surprising choices that shaped the name.
+///
+/// # Arguments
+/// * `name` - The generated name without boundary tokens
+/// * `probs` - Probability of each transition, one more than the number of tokens
+/// * `vocabulary` - Vocabulary of the model, whose tokenizer splits `name` into tokens
+/// * `output_path` - Path where the output image will be saved
+///
+/// # Returns
+/// * `Result<()>` - Ok(()) if the chart was successfully generated and saved, Error otherwise
+///
+/// # Example
+/// ```
+/// use makemore_rs::data::NameItem;
+/// use makemore_rs::plot::plot_trace;
+/// use makemore_rs::tokenizer::Tokenizer;
+/// use makemore_rs::vocabulary::Vocabulary;
+///
+/// let vocab = Vocabulary::new(&[NameItem::new("ava")]);
+/// let path = std::env::temp_dir().join("trace.png");
+/// let path = path.to_str().unwrap();
+/// plot_trace("ava", &[0.14, 0.02, 0.25, 0.2], &vocab, path)?;
+/// assert!(std::fs::metadata(path)?.len() > 0);
+///
+/// // "th" is a single token, so "thea" has four transitions rather than five
+/// let vocab = Vocabulary::with_tokenizer(&[NameItem::new("thea")], Tokenizer::new(&["th"]));
+/// plot_trace("thea", &[0.5, 0.4, 0.3, 0.2], &vocab, path)?;
+/// assert!(plot_trace("thea", &[0.5, 0.4, 0.3, 0.2, 0.1], &vocab, path).is_err());
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn plot_trace(
+    name: &str,
+    probs: &[f32],
+    vocabulary: &Vocabulary,
+    output_path: &str,
+) -> Result<(), MakemoreError> {
+    let mut tokens = vec![".".to_string()];
+    tokens.extend(vocabulary.get_tokenizer().tokenize(name));
+    tokens.push(".".to_string());
+    let labels: Vec<String> = tokens
+        .windows(2)
+        .map(|w| format!("{}{}", w[0], w[1]))
+        .collect();
+    if labels.len() != probs.len() {
+        return Err(MakemoreError::ShapeMismatch {
+            expected: format!("{} transition probabilities for {:?}", labels.len(), name),
+            actual: probs.len().to_string(),
+        });
+    }
+    let n = labels.len();
+
+    let root = BitMapBackend::new(output_path, (800, 600)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(
+            format!("Transition probabilities for {}", name),
+            ("sans-serif", 30),
+        )
+        .margin(40)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(-0.5f32..(n as f32 - 0.5), 0f32..1f32)?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_labels(n)
+        .x_label_style(("sans-serif", 15))
+        .y_label_style(("sans-serif", 15))
+        .x_label_formatter(&|x| labels[(x.round().max(0.0) as usize).min(n - 1)].clone())
+        .y_desc("Probability")
+        .draw()?;
+
+    chart.draw_series(probs.iter().enumerate().map(|(i, &p)| {
+        Rectangle::new(
+            [(i as f32 - 0.4, 0.0), (i as f32 + 0.4, p)],
+            RED.mix(0.7).filled(),
+        )
+    }))?;
+
+    root.present()?;
+    println!("Trace chart saved as {}", output_path);
+    Ok(())
+}
+
+/// Draws the column sums of `data` as vertical bars in `column_area` and the row sums as
+/// horizontal bars in `row_area`, aligned with the heatmap grid.
+fn draw_marginals(
+    column_area: &DrawingArea<BitMapBackend, Shift>,
+    row_area: &DrawingArea<BitMapBackend, Shift>,
+    data: &[Vec<f64>],
+) -> Result<(), MakemoreError> {
+    let n = data.len();
+    let row_sums: Vec<f64> = data.iter().map(|row| row.iter().sum()).collect();
+    let column_sums: Vec<f64> = (0..n)
+        .map(|j| data.iter().map(|row| row[j]).sum())
+        .collect();
+    let max_row = row_sums.iter().fold(f64::MIN_POSITIVE, |a, &b| a.max(b));
+    let max_column = column_sums.iter().fold(f64::MIN_POSITIVE, |a, &b| a.max(b));
+
+    // Left and right margins match the heatmap so the bars line up with its columns
+    let mut column_chart = ChartBuilder::on(column_area)
+        .margin(10)
+        .margin_left(60)
+        .margin_right(60)
+        .y_label_area_size(60)
+        .build_cartesian_2d(-0.5f32..(n as f32 - 0.5), 0f64..max_column)?;
+    column_chart.draw_series(column_sums.iter().enumerate().map(|(j, &sum)| {
+        Rectangle::new(
+            [(j as f32 - 0.4, 0.0), (j as f32 + 0.4, sum)],
+            BLUE.mix(0.6).filled(),
+        )
+    }))?;
+
+    // Top and bottom margins match the heatmap so the bars line up with its rows
+    let mut row_chart = ChartBuilder::on(row_area)
+        .margin(60)
+        .margin_left(10)
+        .margin_right(10)
+        .x_label_area_size(60)
+        .build_cartesian_2d(0f64..max_row, (n as f32 - 0.5)..(-0.5f32))?;
+    row_chart.draw_series(row_sums.iter().enumerate().map(|(i, &sum)| {
+        Rectangle::new(
+            [(0.0, i as f32 - 0.4), (sum, i as f32 + 0.4)],
+            BLUE.mix(0.6).filled(),
+        )
+    }))?;
+
+    Ok(())
+}
+
+/// Lays out bigram values as a dense `n x n` matrix indexed by vocabulary position.
+fn bigram_matrix<T: Into<f64> + Copy>(
+    b: &HashMap<(String, String), T>,
+    n: usize,
+    char_to_idx: &HashMap<String, usize>,
+) -> Vec<Vec<f64>> {
+    let mut data = vec![vec![0.0; n]; n];
+    for ((ch1, ch2), count) in b {
+        let i = char_to_idx[ch1];
+        let j = char_to_idx[ch2];
+        data[i][j] = (*count).into();
+    }
+    data
+}
+
+/// Lays out the element-wise difference `a - b` of two bigram maps as a dense `n x n`
+/// matrix, treating bigrams missing from either map as 0.
+pub(crate) fn bigram_diff_matrix<T: Into<f64> + Copy>(
+    a: &HashMap<(String, String), T>,
+    b: &HashMap<(String, String), T>,
+    n: usize,
+    char_to_idx: &HashMap<String, usize>,
+) -> Vec<Vec<f64>> {
+    let a = bigram_matrix(a, n, char_to_idx);
+    let b = bigram_matrix(b, n, char_to_idx);
+    a.iter()
+        .zip(&b)
+        .map(|(row_a, row_b)| row_a.iter().zip(row_b).map(|(x, y)| x - y).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bigrams::BigramModel;
+    use candle_core::Device;
+
+    #[test]
+    fn self_diff_is_zero_everywhere() {
+        let model = BigramModel::from_words(&["emma", "ava"], &Device::Cpu).unwrap();
+        let probs = model.get_probabilities_map().unwrap();
+        let n = model.get_chars().len();
+        let char_to_idx = model.get_vocabulary().get_char_to_idx();
+
+        let diff = bigram_diff_matrix(&probs, &probs, n, char_to_idx);
+        assert_eq!(diff.len(), n);
+        assert!(diff.iter().all(|row| row.len() == n));
+        assert!(diff.iter().flatten().all(|&value| value == 0.0));
+    }
+
+    #[test]
+    fn diff_subtracts_missing_bigrams_as_zero() {
+        let char_to_idx: HashMap<String, usize> = [(".", 0), ("a", 1)]
+            .into_iter()
+            .map(|(c, i)| (c.to_string(), i))
+            .collect();
+        let pair = |a: &str, b: &str| (a.to_string(), b.to_string());
+        let a = HashMap::from([(pair(".", "a"), 3), (pair("a", "."), 1)]);
+        let b = HashMap::from([(pair(".", "a"), 1)]);
+
+        let diff = bigram_diff_matrix(&a, &b, 2, &char_to_idx);
+        assert_eq!(diff, vec![vec![0.0, 2.0], vec![1.0, 0.0]]);
+    }
+}
@@ -0,0 +1,210 @@
+//! Position-aware bigram model that conditions the next-character distribution on both
+//! the previous character and where in the word the transition happens.
+
+use crate::bigrams::MAX_SAMPLE_LEN;
+use crate::data::NameItem;
+use crate::utils::{mask_end_token, sample_from_probs};
+use crate::vocabulary::Vocabulary;
+use crate::MakemoreError;
+use candle_core::{Device, IndexOp, Tensor};
+use rand::Rng;
+
+/// A bigram model keyed on `(previous character, position bucket)`.
+///
+/// Transition positions are split into `num_buckets` equal ranges spanning the longest
+/// training name, so with three buckets the model keeps separate statistics for the
+/// start, middle and end of words. Positions past the longest training name fall in the
+/// last bucket. This captures effects that pure bigrams miss, such as certain letters
+/// being more likely to end a word.
+#[derive(Debug, Clone)]
+pub struct PositionalBigramModel {
+    vocabulary: Vocabulary,
+    num_buckets: usize,
+    /// Number of transitions in the longest training name
+    max_transitions: usize,
+    /// Counts of shape `[num_buckets, vocab_size, vocab_size]`
+    count_tensor: Tensor,
+    /// Probabilities of shape `[num_buckets, vocab_size, vocab_size]`, each row summing to 1
+    probabilities: Tensor,
+}
+
+impl PositionalBigramModel {
+    /// Creates a new PositionalBigramModel with computed frequencies and probabilities
+    ///
+    /// # Arguments
+    /// * `names` - Slice of name items used to build the vocabulary
+    /// * `num_buckets` - Number of position buckets
+    /// * `device` - Device to store tensors on (CPU/GPU)
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidArgument`] if `num_buckets` is zero
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::positional::PositionalBigramModel;
+    ///
+    /// let names = vec![
+    ///     NameItem::new("abababa"),
+    ///     NameItem::new("ba"),
+    ///     NameItem::new("ab"),
+    /// ];
+    /// let model = PositionalBigramModel::new(&names, 2, &Device::Cpu)?;
+    ///
+    /// // 'a' ends words more often late in the word than early on
+    /// let early = model.probability("a", 0, ".")?;
+    /// let late = model.probability("a", 1, ".")?;
+    /// assert!(late > early);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn new(
+        names: &[NameItem],
+        num_buckets: usize,
+        device: &Device,
+    ) -> Result<Self, MakemoreError> {
+        if num_buckets == 0 {
+            return Err(MakemoreError::InvalidArgument(
+                "At least one position bucket is required".to_string(),
+            ));
+        }
+
+        let vocabulary = Vocabulary::new(names);
+        let vocab_size = vocabulary.get_size();
+        let max_transitions = names
+            .iter()
+            .map(|name| name.name.chars().count() + 1)
+            .max()
+            .unwrap_or(1);
+
+        let mut counts = vec![0f32; num_buckets * vocab_size * vocab_size];
+        for name in names {
+            let tokens: Vec<usize> = std::iter::once(0)
+                .chain(vocabulary.encode(&name.name)?)
+                .chain(std::iter::once(0))
+                .collect();
+            for (position, window) in tokens.windows(2).enumerate() {
+                let bucket = Self::bucket_for(position, num_buckets, max_transitions);
+                let offset = (bucket * vocab_size + window[0]) * vocab_size;
+                counts[offset + window[1]] += name.weight;
+            }
+        }
+
+        // Normalize each (bucket, previous character) row, leaving unseen rows at zero
+        let mut probabilities = counts.clone();
+        for row in probabilities.chunks_mut(vocab_size) {
+            let sum: f32 = row.iter().sum();
+            if sum > 0.0 {
+                row.iter_mut().for_each(|p| *p /= sum);
+            }
+        }
+
+        let shape = (num_buckets, vocab_size, vocab_size);
+        Ok(Self {
+            vocabulary,
+            num_buckets,
+            max_transitions,
+            count_tensor: Tensor::from_vec(counts, shape, device)?,
+            probabilities: Tensor::from_vec(probabilities, shape, device)?,
+        })
+    }
+
+    pub fn get_vocabulary(&self) -> &Vocabulary {
+        &self.vocabulary
+    }
+
+    pub fn get_tensor(&self) -> &Tensor {
+        &self.count_tensor
+    }
+
+    pub fn get_probabilities(&self) -> &Tensor {
+        &self.probabilities
+    }
+
+    /// Returns the position bucket of the transition at `position` (0 is the transition
+    /// out of the '.' start token).
+    pub fn bucket(&self, position: usize) -> usize {
+        Self::bucket_for(position, self.num_buckets, self.max_transitions)
+    }
+
+    /// Returns the probability of `next` following `prev` within position bucket `bucket`.
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if either character is not in the vocabulary
+    /// * [`MakemoreError::IndexOutOfRange`] if the bucket is out of range
+    pub fn probability(&self, prev: &str, bucket: usize, next: &str) -> Result<f32, MakemoreError> {
+        let i = self.vocabulary.get_index(prev)?;
+        let j = self.vocabulary.get_index(next)?;
+        if bucket >= self.num_buckets {
+            return Err(MakemoreError::IndexOutOfRange {
+                index: bucket as i64,
+                size: self.num_buckets,
+            });
+        }
+        Ok(self.probabilities.i((bucket, i, j))?.to_scalar::<f32>()?)
+    }
+
+    /// Samples a single name, using the bucket of each transition's position to pick
+    /// the conditional distribution. The end token cannot be drawn on the first step, so
+    /// names are never empty, and names stop after 50 characters.
+    ///
+    /// A character that was never seen within the bucket of the current position has no
+    /// distribution there, so the next character is drawn from its counts pooled over
+    /// all buckets instead.
+    ///
+    /// # Arguments
+    /// * `rng` - Random number generator used for sampling
+    ///
+    /// # Returns
+    /// * The generated name without boundary tokens
+    ///
+    /// # Errors
+    /// * [`MakemoreError::InvalidProbabilities`] if a probability row reached while sampling
+    ///   has no mass or holds a negative or NaN value
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::positional::PositionalBigramModel;
+    ///
+    /// let names = vec![NameItem::new("ab"), NameItem::new("ca"), NameItem::new("ccca")];
+    /// let model = PositionalBigramModel::new(&names, 2, &Device::Cpu)?;
+    ///
+    /// // 'b' only occurs in the first bucket, but "cab" reaches it in the second one
+    /// let mut rng = rand::thread_rng();
+    /// for _ in 0..100 {
+    ///     let name = model.sample_name(&mut rng)?;
+    ///     assert!((1..=50).contains(&name.chars().count()));
+    /// }
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn sample_name<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<String, MakemoreError> {
+        let mut name = String::new();
+        let mut ix = 0;
+
+        for position in 0..MAX_SAMPLE_LEN {
+            let mut row = self
+                .probabilities
+                .i((self.bucket(position), ix))?
+                .to_vec1::<f32>()?;
+            if row.iter().all(|&p| p == 0.0) {
+                row = self.count_tensor.i((.., ix))?.sum(0)?.to_vec1::<f32>()?;
+            }
+            mask_end_token(&mut row, position, 0);
+            ix = sample_from_probs(&row, rng)?;
+            if ix == 0 {
+                break;
+            }
+            name.push_str(self.vocabulary.get_char(ix));
+        }
+
+        Ok(name)
+    }
+
+    fn bucket_for(position: usize, num_buckets: usize, max_transitions: usize) -> usize {
+        (position * num_buckets / max_transitions).min(num_buckets - 1)
+    }
+}
@@ -0,0 +1,81 @@
+//! Splitting names into tokens, optionally treating registered multi-character
+//! sequences such as digraphs as single tokens.
+
+/// Splits words into tokens by greedily matching registered multi-character tokens
+/// before falling back to single characters.
+///
+/// At each position the longest registered token that matches is taken; if none
+/// matches, the single character at that position becomes a token. The default
+/// tokenizer has no multi-character tokens, so every character is its own token.
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::tokenizer::Tokenizer;
+///
+/// let tokenizer = Tokenizer::new(&["th", "ch"]);
+/// assert_eq!(tokenizer.tokenize("the"), vec!["th", "e"]);
+/// assert_eq!(tokenizer.tokenize("tchad"), vec!["t", "ch", "a", "d"]);
+/// assert_eq!(Tokenizer::default().tokenize("the"), vec!["t", "h", "e"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tokenizer {
+    /// Registered multi-character tokens, longest first so greedy matching prefers them
+    multi_char_tokens: Vec<String>,
+}
+
+impl Tokenizer {
+    /// Creates a tokenizer that treats each of `tokens` as a single token.
+    ///
+    /// Tokens of at most one character add nothing over the single-character fallback
+    /// and are ignored, as are duplicates.
+    ///
+    /// # Arguments
+    /// * `tokens` - Multi-character tokens to match, e.g. `["th", "ch"]`
+    pub fn new(tokens: &[&str]) -> Self {
+        let mut multi_char_tokens: Vec<String> = tokens
+            .iter()
+            .filter(|token| token.chars().count() > 1)
+            .map(|token| token.to_string())
+            .collect();
+        multi_char_tokens.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        multi_char_tokens.dedup();
+        Self { multi_char_tokens }
+    }
+
+    /// Returns the registered multi-character tokens, longest first.
+    pub fn get_multi_char_tokens(&self) -> &[String] {
+        &self.multi_char_tokens
+    }
+
+    /// Splits `word` into tokens.
+    pub fn tokenize(&self, word: &str) -> Vec<String> {
+        let mut tokens = Vec::new();
+        let mut rest = word;
+
+        while let Some(c) = rest.chars().next() {
+            let token = self
+                .multi_char_tokens
+                .iter()
+                .find(|token| rest.starts_with(token.as_str()))
+                .map(String::as_str)
+                .unwrap_or(&rest[..c.len_utf8()]);
+            tokens.push(token.to_string());
+            rest = &rest[token.len()..];
+        }
+
+        tokens
+    }
+
+    /// Returns a tokenizer that matches the multi-character tokens of both `self` and
+    /// `other`.
+    pub fn union(&self, other: &Tokenizer) -> Tokenizer {
+        let tokens: Vec<&str> = self
+            .multi_char_tokens
+            .iter()
+            .chain(&other.multi_char_tokens)
+            .map(String::as_str)
+            .collect();
+        Tokenizer::new(&tokens)
+    }
+}
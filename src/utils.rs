@@ -1,6 +1,10 @@
-use anyhow::Result;
-use candle_core::Tensor;
+use crate::MakemoreError;
+use candle_core::{DType, Device, Tensor};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
 use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
 
 pub fn init_logging() {
     tracing_subscriber::fmt()
@@ -8,11 +12,113 @@ pub fn init_logging() {
         .init();
 }
 
+/// Lists the devices this build can run on.
+///
+/// The CPU is always available. The first CUDA and Metal devices are added when the crate
+/// is built with the `cuda` or `metal` feature and the device can actually be opened;
+/// failures to open one are logged and the device is skipped.
+///
+/// # Examples
+///
+/// ```
+/// use candle_core::Tensor;
+/// use makemore_rs::utils::available_devices;
+///
+/// let devices = available_devices();
+/// assert!(devices[0].is_cpu());
+/// for device in &devices {
+///     let tensor = Tensor::ones((2, 3), candle_core::DType::F32, device)?;
+///     assert_eq!(tensor.sum_all()?.to_scalar::<f32>()?, 6.0);
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn available_devices() -> Vec<Device> {
+    #[allow(unused_mut)]
+    let mut devices = vec![Device::Cpu];
+
+    #[cfg(feature = "cuda")]
+    match Device::new_cuda(0) {
+        Ok(device) => devices.push(device),
+        Err(e) => tracing::warn!("CUDA device unavailable: {}", e),
+    }
+
+    #[cfg(feature = "metal")]
+    match Device::new_metal(0) {
+        Ok(device) => devices.push(device),
+        Err(e) => tracing::warn!("Metal device unavailable: {}", e),
+    }
+
+    devices
+}
+
+/// Reads and parses the environment variable `name`, falling back to `default` if it is
+/// not set.
+///
+/// # Errors
+/// * [`MakemoreError::InvalidArgument`] if the variable is set but cannot be parsed as `T`
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::utils::env_or;
+///
+/// std::env::remove_var("MAKEMORE_DOC_SAMPLES");
+/// assert_eq!(env_or("MAKEMORE_DOC_SAMPLES", 5usize)?, 5);
+///
+/// std::env::set_var("MAKEMORE_DOC_SAMPLES", "12");
+/// assert_eq!(env_or("MAKEMORE_DOC_SAMPLES", 5usize)?, 12);
+///
+/// std::env::set_var("MAKEMORE_DOC_SAMPLES", "many");
+/// assert!(env_or("MAKEMORE_DOC_SAMPLES", 5usize).is_err());
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn env_or<T>(name: &str, default: T) -> Result<T, MakemoreError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match std::env::var(name) {
+        Ok(value) => match value.trim().parse() {
+            Ok(parsed) => Ok(parsed),
+            Err(e) => Err(MakemoreError::InvalidArgument(format!(
+                "Invalid value {:?} for {}: {}",
+                value, name, e
+            ))),
+        },
+        Err(_) => Ok(default),
+    }
+}
+
+/// Converts a `[vocab, vocab]` tensor into a map from character pairs to their values.
+///
+/// Only strictly positive entries are included. The tensor is converted to F64
+/// internally, so both F32 (as stored by `BigramModel`) and F64 tensors are accepted.
+///
+/// # Arguments
+/// * `tensor` - 2-D tensor of bigram values
+/// * `chars` - Vocabulary characters labelling the rows and columns
+///
+/// # Examples
+///
+/// ```
+/// use candle_core::{DType, Device, Tensor};
+/// use makemore_rs::utils::tensor_to_bigram_hashmap;
+///
+/// let chars = vec![".".to_string(), "a".to_string()];
+/// let f32_tensor = Tensor::new(&[[0f32, 3.0], [1.0, 0.0]], &Device::Cpu)?;
+/// let f64_tensor = f32_tensor.to_dtype(DType::F64)?;
+///
+/// let from_f32 = tensor_to_bigram_hashmap(&f32_tensor, &chars)?;
+/// let from_f64 = tensor_to_bigram_hashmap(&f64_tensor, &chars)?;
+/// assert_eq!(from_f32, from_f64);
+/// assert_eq!(from_f32[&(".".to_string(), "a".to_string())], 3.0);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
 pub fn tensor_to_bigram_hashmap(
     tensor: &Tensor,
     chars: &[String],
-) -> Result<HashMap<(String, String), f64>> {
-    let data = tensor.to_vec2::<f64>()?;
+) -> Result<HashMap<(String, String), f64>, MakemoreError> {
+    let data = tensor.to_dtype(DType::F64)?.to_vec2::<f64>()?;
     let mut bigram_map = HashMap::new();
 
     for (i, row) in data.iter().enumerate() {
@@ -25,3 +131,122 @@ pub fn tensor_to_bigram_hashmap(
 
     Ok(bigram_map)
 }
+
+/// Zeroes the weight of the '.' end token while fewer than `min_len` characters, and
+/// always while no character, have been generated, so sampled names are never empty.
+/// A row whose only mass is on the end token is left alone, so the name ends there
+/// instead of leaving nothing to sample.
+pub(crate) fn mask_end_token(weights: &mut [f32], len: usize, min_len: usize) {
+    if len < min_len.max(1) && weights[1..].iter().any(|&w| w > 0.0) {
+        weights[0] = 0.0;
+    }
+}
+
+/// Samples an index from a discrete distribution using `WeightedIndex`.
+///
+/// The weights do not need to sum to 1. Invalid input produces an error instead of the
+/// panic or opaque `WeightedError` that building a `WeightedIndex` inline would give.
+///
+/// # Arguments
+/// * `probs` - Non-negative weights, one per index
+/// * `rng` - Random number generator used for sampling
+///
+/// # Errors
+/// * [`MakemoreError::InvalidProbabilities`] if `probs` is empty, contains a negative or
+///   non-finite value, or sums to zero
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::utils::sample_from_probs;
+///
+/// let mut rng = rand::thread_rng();
+/// assert_eq!(sample_from_probs(&[0.0, 1.0, 0.0], &mut rng)?, 1);
+/// assert!(sample_from_probs(&[0.2, 0.5, 0.3], &mut rng)? < 3);
+///
+/// assert!(sample_from_probs(&[], &mut rng).is_err());
+/// assert!(sample_from_probs(&[0.0, 0.0], &mut rng).is_err());
+/// assert!(sample_from_probs(&[0.5, f32::NAN], &mut rng).is_err());
+/// assert!(sample_from_probs(&[0.5, -0.1], &mut rng).is_err());
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn sample_from_probs<R: Rng + ?Sized>(
+    probs: &[f32],
+    rng: &mut R,
+) -> Result<usize, MakemoreError> {
+    if probs.is_empty() {
+        return Err(MakemoreError::InvalidProbabilities(
+            "cannot sample from an empty distribution".to_string(),
+        ));
+    }
+    if let Some(ix) = probs.iter().position(|p| !p.is_finite() || *p < 0.0) {
+        return Err(MakemoreError::InvalidProbabilities(format!(
+            "invalid probability {} at index {}",
+            probs[ix], ix
+        )));
+    }
+    if probs.iter().all(|&p| p == 0.0) {
+        return Err(MakemoreError::InvalidProbabilities(
+            "cannot sample from a distribution with all-zero weights".to_string(),
+        ));
+    }
+
+    let dist = WeightedIndex::new(probs)
+        .map_err(|e| MakemoreError::InvalidProbabilities(e.to_string()))?;
+    Ok(dist.sample(rng))
+}
+
+/// Returns the `k` bigrams whose probabilities differ most between two maps.
+///
+/// A bigram missing from one map counts as probability 0 there. Results are sorted by
+/// descending absolute difference, with ties broken by the bigram so the order is
+/// deterministic. This is a textual counterpart to
+/// [`plot_bigram_diff`](crate::plot::plot_bigram_diff).
+///
+/// # Arguments
+/// * `a` - First map from character pairs to probabilities
+/// * `b` - Second map from character pairs to probabilities
+/// * `k` - Maximum number of bigrams to return
+///
+/// # Returns
+/// * Each bigram with its probability in `a` and in `b`
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::utils::top_probability_differences;
+/// use std::collections::HashMap;
+///
+/// let pair = |x: &str, y: &str| (x.to_string(), y.to_string());
+/// let a = HashMap::from([(pair("a", "b"), 0.5), (pair("b", "."), 0.9), (pair(".", "a"), 1.0)]);
+/// let b = HashMap::from([(pair("a", "b"), 0.4), (pair("b", "."), 0.2), (pair("a", "."), 0.6)]);
+///
+/// let diffs = top_probability_differences(&a, &b, 2);
+/// assert_eq!(diffs.len(), 2);
+/// assert_eq!(diffs[0], (pair(".", "a"), 1.0, 0.0));
+/// assert_eq!(diffs[1], (pair("b", "."), 0.9, 0.2));
+/// ```
+pub fn top_probability_differences(
+    a: &HashMap<(String, String), f32>,
+    b: &HashMap<(String, String), f32>,
+    k: usize,
+) -> Vec<((String, String), f32, f32)> {
+    let mut diffs: Vec<((String, String), f32, f32)> = a
+        .keys()
+        .chain(b.keys().filter(|pair| !a.contains_key(*pair)))
+        .map(|pair| {
+            let p_a = a.get(pair).copied().unwrap_or(0.0);
+            let p_b = b.get(pair).copied().unwrap_or(0.0);
+            (pair.clone(), p_a, p_b)
+        })
+        .collect();
+
+    diffs.sort_by(|(pair_x, a_x, b_x), (pair_y, a_y, b_y)| {
+        (a_y - b_y)
+            .abs()
+            .total_cmp(&(a_x - b_x).abs())
+            .then_with(|| pair_x.cmp(pair_y))
+    });
+    diffs.truncate(k);
+    diffs
+}
@@ -1,6 +1,11 @@
 use std::collections::{HashMap, HashSet};
 
 use crate::data::NameItem;
+use crate::tokenizer::Tokenizer;
+use crate::MakemoreError;
+
+/// Token standing in for characters that were not seen when the vocabulary was built
+pub const UNK_TOKEN: &str = "<unk>";
 
 /// A vocabulary that maps characters to indices and vice versa.
 ///
@@ -12,6 +17,14 @@ use crate::data::NameItem;
 /// The vocabulary always includes a special "." character that is guaranteed to be at index 0,
 /// which can be used as a start/end token or padding character.
 ///
+/// Vocabularies built with [`Vocabulary::with_tokenizer`] also contain the multi-character
+/// tokens of their [`Tokenizer`], and [`Vocabulary::encode`] splits words with it. The
+/// "characters" of such a vocabulary are really tokens.
+///
+/// Vocabularies built with [`Vocabulary::with_unk`] also reserve a final slot for
+/// [`UNK_TOKEN`], which [`Vocabulary::encode_with_unk`] maps unseen characters to. The
+/// plain [`Vocabulary::new`] does not reserve it.
+///
 /// # Examples
 ///
 /// ```
@@ -19,8 +32,8 @@ use crate::data::NameItem;
 /// use makemore_rs::vocabulary::Vocabulary;
 ///
 /// let names = vec![
-///     NameItem { name: "Alice".to_string() },
-///     NameItem { name: "Bob".to_string() }
+///     NameItem::new("Alice"),
+///     NameItem::new("Bob"),
 /// ];
 ///
 /// let vocab = Vocabulary::new(&names);
@@ -33,6 +46,12 @@ pub struct Vocabulary {
     chars: Vec<String>,
     /// Mapping from characters to their corresponding indices in the vocabulary
     char_to_idx: HashMap<String, usize>,
+    /// Total number of character occurrences in the names the vocabulary was built from
+    total_occurrences: usize,
+    /// Index of the reserved [`UNK_TOKEN`] slot, if the vocabulary has one
+    unk_index: Option<usize>,
+    /// Splits words into the tokens the vocabulary indexes
+    tokenizer: Tokenizer,
 }
 
 impl Vocabulary {
@@ -50,14 +69,141 @@ impl Vocabulary {
     ///
     /// A new Vocabulary instance containing all unique characters from the names
     pub fn new(names: &[NameItem]) -> Self {
-        let chars = Self::build_chars(names);
-        let char_to_idx = chars
-            .iter()
-            .enumerate()
-            .map(|(i, c)| (c.clone(), i))
-            .collect();
+        Self::with_tokenizer(names, Tokenizer::default())
+    }
+
+    /// Creates a new vocabulary whose entries are the tokens produced by `tokenizer`.
+    ///
+    /// Registered multi-character tokens (e.g. digraphs such as "th") only become part of
+    /// the vocabulary if they occur in the names. The tokens are sorted alphabetically
+    /// with "." first, as in [`Vocabulary::new`].
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - A slice of NameItems to build the vocabulary from
+    /// * `tokenizer` - Tokenizer used to split the names and, later, words to encode
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::tokenizer::Tokenizer;
+    /// use makemore_rs::vocabulary::Vocabulary;
+    ///
+    /// let vocab = Vocabulary::with_tokenizer(&[NameItem::new("theo")], Tokenizer::new(&["th"]));
+    /// assert_eq!(vocab.get_chars(), &vec![".", "e", "o", "th"]);
+    /// assert_eq!(vocab.encode("the")?, vec![3, 1]);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn with_tokenizer(names: &[NameItem], tokenizer: Tokenizer) -> Self {
+        let mut builder = VocabularyBuilder::new().with_tokenizer(tokenizer);
+        for name in names {
+            builder.add_name(&name.name);
+        }
+        builder.build()
+    }
+
+    /// Creates a new vocabulary with an extra [`UNK_TOKEN`] slot for unseen characters.
+    ///
+    /// The characters are laid out as in [`Vocabulary::new`], with the unknown token
+    /// appended as the last index.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - A slice of NameItems to build the vocabulary from
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::vocabulary::{Vocabulary, UNK_TOKEN};
+    ///
+    /// let vocab = Vocabulary::with_unk(&[NameItem::new("emma")]);
+    /// let unk = vocab.get_unk_index().unwrap();
+    /// assert_eq!(vocab.get_char(unk), UNK_TOKEN);
+    ///
+    /// // 'z' was never seen, so it lands in the reserved slot
+    /// assert_eq!(vocab.encode_with_unk("emz")?, vec![2, 3, unk]);
+    /// assert!(vocab.encode("emz").is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn with_unk(names: &[NameItem]) -> Self {
+        let mut vocabulary = Self::new(names);
+        vocabulary.push_unk();
+        vocabulary
+    }
+
+    /// Creates a vocabulary from an explicit, ordered list of characters.
+    ///
+    /// Unlike [`Vocabulary::new`], nothing is inferred from data, so the vocabulary (and
+    /// any model trained with it) has the same size whichever characters the names
+    /// actually contain. Indices follow the order of `chars`. Entries longer than one
+    /// character are matched as multi-character tokens when encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `chars` - The alphabet, starting with "."
+    ///
+    /// # Errors
+    ///
+    /// * [`MakemoreError::InvalidArgument`] if "." is missing or not the first entry, since
+    ///   index 0 is reserved for it, or if an entry is empty or appears more than once
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use candle_core::Device;
+    /// use makemore_rs::bigrams::BigramModel;
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::vocabulary::Vocabulary;
+    ///
+    /// let alphabet: Vec<String> = std::iter::once('.').chain('a'..='z').map(String::from).collect();
+    /// let alphabet: Vec<&str> = alphabet.iter().map(String::as_str).collect();
+    /// let vocab = Vocabulary::from_chars(&alphabet)?;
+    /// assert_eq!(vocab.get_size(), 27);
+    /// assert_eq!(vocab.encode("emma")?, vec![5, 13, 13, 1]);
+    ///
+    /// // Letters missing from the names still get a row and column
+    /// let names = vec![NameItem::new("emma"), NameItem::new("ava")];
+    /// let model = BigramModel::new_with_vocabulary(&names, vocab, &Device::Cpu)?;
+    /// assert_eq!(model.get_tensor().dims(), &[27, 27]);
+    ///
+    /// assert!(Vocabulary::from_chars(&["a", "b"]).is_err());
+    /// assert!(Vocabulary::from_chars(&["a", ".", "b"]).is_err());
+    /// assert!(Vocabulary::from_chars(&[".", "a", "a"]).is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn from_chars(chars: &[&str]) -> Result<Self, MakemoreError> {
+        if chars.first() != Some(&".") {
+            return Err(MakemoreError::InvalidArgument(format!(
+                "The alphabet must start with \".\", got {:?}",
+                chars
+            )));
+        }
 
-        Self { chars, char_to_idx }
+        let mut char_to_idx = HashMap::with_capacity(chars.len());
+        for (i, &c) in chars.iter().enumerate() {
+            if c.is_empty() {
+                return Err(MakemoreError::InvalidArgument(format!(
+                    "The alphabet contains an empty entry at index {}",
+                    i
+                )));
+            }
+            if char_to_idx.insert(c.to_string(), i).is_some() {
+                return Err(MakemoreError::InvalidArgument(format!(
+                    "The alphabet contains {:?} more than once",
+                    c
+                )));
+            }
+        }
+
+        Ok(Self {
+            chars: chars.iter().map(|c| c.to_string()).collect(),
+            char_to_idx,
+            total_occurrences: 0,
+            unk_index: None,
+            tokenizer: Tokenizer::new(chars),
+        })
     }
 
     /// Builds a sorted vector of unique characters from the provided names.
@@ -75,27 +221,271 @@ impl Vocabulary {
     ///
     /// A sorted vector of unique characters as Strings
     pub fn build_chars(names: &[NameItem]) -> Vec<String> {
-        let mut chars: Vec<String> = names
-            .iter()
-            .flat_map(|name| name.name.chars())
-            .map(|c| c.to_string())
-            .collect::<HashSet<_>>()
+        Self::sort_chars(
+            names
+                .iter()
+                .flat_map(|name| name.name.chars())
+                .map(|c| c.to_string())
+                .collect(),
+        )
+    }
+
+    /// Converts a word into the vocabulary indices of its characters.
+    ///
+    /// No boundary tokens are added; the result has one index per character.
+    ///
+    /// # Arguments
+    ///
+    /// * `word` - The word to encode
+    ///
+    /// # Errors
+    ///
+    /// [`MakemoreError::NotInVocabulary`] with the first token of the word that is
+    /// outside the vocabulary
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::vocabulary::Vocabulary;
+    /// use makemore_rs::MakemoreError;
+    ///
+    /// let vocab = Vocabulary::new(&[NameItem::new("emma")]);
+    /// let indices = vocab.encode("emma")?;
+    /// assert_eq!(indices, vec![2, 3, 3, 1]);
+    /// assert_eq!(vocab.decode(&indices), "emma");
+    ///
+    /// assert!(matches!(
+    ///     vocab.encode("emily"),
+    ///     Err(MakemoreError::NotInVocabulary(token)) if token == "i"
+    /// ));
+    /// # Ok::<(), MakemoreError>(())
+    /// ```
+    pub fn encode(&self, word: &str) -> Result<Vec<usize>, MakemoreError> {
+        self.tokenizer
+            .tokenize(word)
             .into_iter()
-            .chain(std::iter::once(".".to_string()))
+            .map(|c| self.get_index(&c))
+            .collect()
+    }
+
+    /// Converts a word into vocabulary indices, mapping unseen characters to the
+    /// [`UNK_TOKEN`] slot.
+    ///
+    /// # Arguments
+    ///
+    /// * `word` - The word to encode
+    ///
+    /// # Errors
+    ///
+    /// [`MakemoreError::NotInVocabulary`] if the vocabulary was built without an unknown
+    /// token slot
+    pub fn encode_with_unk(&self, word: &str) -> Result<Vec<usize>, MakemoreError> {
+        let unk_index = self
+            .unk_index
+            .ok_or_else(|| MakemoreError::NotInVocabulary(UNK_TOKEN.to_string()))?;
+        Ok(self
+            .tokenizer
+            .tokenize(word)
+            .into_iter()
+            .map(|c| self.char_to_idx.get(&c).copied().unwrap_or(unk_index))
+            .collect())
+    }
+
+    /// Converts a sequence of indices back into a string.
+    ///
+    /// Each index is mapped to its character and the "." boundary tokens are dropped,
+    /// so a generated sequence such as `[5, 13, 13, 1, 0]` decodes to a plain name.
+    ///
+    /// # Arguments
+    ///
+    /// * `indices` - Vocabulary indices to decode
+    ///
+    /// # Returns
+    ///
+    /// The decoded string without boundary tokens
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::vocabulary::Vocabulary;
+    ///
+    /// let vocab = Vocabulary::new(&[NameItem::new("emma")]);
+    /// let indices: Vec<usize> = ".emma."
+    ///     .chars()
+    ///     .map(|c| vocab.get_char_to_idx()[&c.to_string()])
+    ///     .collect();
+    ///
+    /// assert_eq!(vocab.decode(&indices), "emma");
+    /// ```
+    pub fn decode(&self, indices: &[usize]) -> String {
+        indices
+            .iter()
+            .map(|&ix| self.get_char(ix))
+            .filter(|c| c.as_str() != ".")
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Combines two vocabularies into one containing the characters of both.
+    ///
+    /// Alongside the combined vocabulary this returns the index remapping for each
+    /// input: `remap_self[i]` is the index in the union of `self`'s character `i`, and
+    /// likewise for `remap_other`. These are what is needed to realign count tensors
+    /// built against the original vocabularies. If either input has an [`UNK_TOKEN`]
+    /// slot, the union keeps it as its last index. The union's tokenizer matches the
+    /// multi-character tokens of both inputs.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The vocabulary to combine with this one
+    ///
+    /// # Returns
+    ///
+    /// A tuple of `(union, remap_self, remap_other)`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::vocabulary::Vocabulary;
+    ///
+    /// let a = Vocabulary::new(&[NameItem::new("abc")]);
+    /// let b = Vocabulary::new(&[NameItem::new("bcd")]);
+    /// let (union, remap_a, remap_b) = a.union(&b);
+    ///
+    /// assert_eq!(union.get_chars(), &vec![".", "a", "b", "c", "d"]);
+    /// assert_eq!(remap_a, vec![0, 1, 2, 3]);
+    /// assert_eq!(remap_b, vec![0, 2, 3, 4]);
+    /// ```
+    pub fn union(&self, other: &Vocabulary) -> (Vocabulary, Vec<usize>, Vec<usize>) {
+        let chars = Self::sort_chars(
+            self.chars
+                .iter()
+                .chain(other.chars.iter())
+                .filter(|c| c.as_str() != UNK_TOKEN)
+                .cloned()
+                .collect(),
+        );
+        let char_to_idx: HashMap<String, usize> = chars
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.clone(), i))
             .collect();
 
-        chars.sort_by(|a, b| match (a.as_str(), b.as_str()) {
-            (".", _) => std::cmp::Ordering::Less,
-            (_, ".") => std::cmp::Ordering::Greater,
-            _ => a.cmp(b),
-        });
-        chars
+        let mut vocabulary = Self {
+            chars,
+            char_to_idx,
+            total_occurrences: self.total_occurrences + other.total_occurrences,
+            unk_index: None,
+            tokenizer: self.tokenizer.union(&other.tokenizer),
+        };
+        if self.unk_index.is_some() || other.unk_index.is_some() {
+            vocabulary.push_unk();
+        }
+
+        let remap_self = self
+            .chars
+            .iter()
+            .map(|c| vocabulary.char_to_idx[c])
+            .collect();
+        let remap_other = other
+            .chars
+            .iter()
+            .map(|c| vocabulary.char_to_idx[c])
+            .collect();
+        (vocabulary, remap_self, remap_other)
+    }
+
+    /// Reports vocabulary entries that differ only by case, such as "A" and "a".
+    ///
+    /// Datasets mixing "Anna" and "anna" get separate indices for both spellings of a
+    /// character, which is rarely intended. This only reports the collisions; lowercasing
+    /// the names before building the vocabulary is left to the caller.
+    ///
+    /// # Returns
+    ///
+    /// `(lowercase, other)` pairs sorted by the lowercase entry
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::vocabulary::Vocabulary;
+    ///
+    /// let vocab = Vocabulary::new(&[NameItem::new("Anna"), NameItem::new("anna")]);
+    /// assert_eq!(
+    ///     vocab.casing_collisions(),
+    ///     vec![("a".to_string(), "A".to_string())]
+    /// );
+    /// assert!(Vocabulary::new(&[NameItem::new("anna")]).casing_collisions().is_empty());
+    /// ```
+    pub fn casing_collisions(&self) -> Vec<(String, String)> {
+        let mut collisions: Vec<(String, String)> = self
+            .chars
+            .iter()
+            .filter(|c| c.as_str() != UNK_TOKEN)
+            .filter_map(|c| {
+                let lower = c.to_lowercase();
+                (lower != *c && self.char_to_idx.contains_key(&lower)).then(|| (lower, c.clone()))
+            })
+            .collect();
+        collisions.sort();
+        collisions
+    }
+
+    /// Returns the characters of `names` that are not in the vocabulary.
+    ///
+    /// Names containing any of these fail to [`Vocabulary::encode`], so checking a
+    /// dataset up front, e.g. against a fixed alphabet from [`Vocabulary::from_chars`],
+    /// shows what to clean before training. Names are split with the vocabulary's
+    /// tokenizer, so the result holds tokens rather than characters if it has
+    /// multi-character tokens.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - The names to check
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use makemore_rs::data::NameItem;
+    /// use makemore_rs::vocabulary::Vocabulary;
+    ///
+    /// let alphabet: Vec<String> = std::iter::once('.').chain('a'..='z').map(String::from).collect();
+    /// let alphabet: Vec<&str> = alphabet.iter().map(String::as_str).collect();
+    /// let vocab = Vocabulary::from_chars(&alphabet)?;
+    ///
+    /// let names = vec![NameItem::new("emma"), NameItem::new("r2d2")];
+    /// let unknown = vocab.unknown_chars_in(&names);
+    /// assert_eq!(unknown.len(), 1);
+    /// assert!(unknown.contains("2"));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn unknown_chars_in(&self, names: &[NameItem]) -> HashSet<String> {
+        names
+            .iter()
+            .flat_map(|name| self.tokenizer.tokenize(&name.name))
+            .filter(|token| !self.char_to_idx.contains_key(token))
+            .collect()
     }
 
     pub fn get_char(&self, ix: usize) -> &String {
         &self.chars[ix]
     }
 
+    /// Returns the index of `token`.
+    ///
+    /// # Errors
+    /// * [`MakemoreError::NotInVocabulary`] if `token` is not in the vocabulary
+    pub fn get_index(&self, token: &str) -> Result<usize, MakemoreError> {
+        self.char_to_idx
+            .get(token)
+            .copied()
+            .ok_or_else(|| MakemoreError::NotInVocabulary(token.to_string()))
+    }
+
     /// Returns a reference to the vector of characters in the vocabulary.
     ///
     /// The characters are sorted alphabetically with "." always first.
@@ -108,6 +498,24 @@ impl Vocabulary {
         self.chars.len()
     }
 
+    /// Returns the total number of character occurrences in the source names.
+    ///
+    /// Boundary tokens are not counted. For a union of vocabularies this is the
+    /// sum of both totals.
+    pub fn get_total_occurrences(&self) -> usize {
+        self.total_occurrences
+    }
+
+    /// Returns the index of the [`UNK_TOKEN`] slot, or `None` if the vocabulary has none.
+    pub fn get_unk_index(&self) -> Option<usize> {
+        self.unk_index
+    }
+
+    /// Returns the tokenizer used to split words into vocabulary entries.
+    pub fn get_tokenizer(&self) -> &Tokenizer {
+        &self.tokenizer
+    }
+
     /// Returns a reference to the character-to-index mapping.
     ///
     /// This mapping can be used to convert characters to their corresponding
@@ -115,4 +523,100 @@ impl Vocabulary {
     pub fn get_char_to_idx(&self) -> &HashMap<String, usize> {
         &self.char_to_idx
     }
+
+    /// Appends the [`UNK_TOKEN`] slot as the last index.
+    fn push_unk(&mut self) {
+        let unk_index = self.chars.len();
+        self.chars.push(UNK_TOKEN.to_string());
+        self.char_to_idx.insert(UNK_TOKEN.to_string(), unk_index);
+        self.unk_index = Some(unk_index);
+    }
+
+    /// Sorts a set of characters alphabetically, adding "." and placing it first.
+    fn sort_chars(mut chars: HashSet<String>) -> Vec<String> {
+        chars.insert(".".to_string());
+        let mut chars: Vec<String> = chars.into_iter().collect();
+
+        chars.sort_by(|a, b| match (a.as_str(), b.as_str()) {
+            (".", _) => std::cmp::Ordering::Less,
+            (_, ".") => std::cmp::Ordering::Greater,
+            _ => a.cmp(b),
+        });
+        chars
+    }
+}
+
+/// Builds a [`Vocabulary`] incrementally, one name at a time.
+///
+/// Unlike [`Vocabulary::new`], which needs every name up front, the builder only keeps
+/// the set of tokens seen so far, so names can be fed in as they are streamed from a
+/// file. The tokens are sorted once, when [`VocabularyBuilder::build`] is called.
+///
+/// # Examples
+///
+/// ```
+/// use makemore_rs::data::NameItem;
+/// use makemore_rs::vocabulary::{Vocabulary, VocabularyBuilder};
+///
+/// let names = ["emma", "olivia", "ava", "Zoë"];
+///
+/// let mut builder = VocabularyBuilder::new();
+/// for name in names {
+///     builder.add_name(name);
+/// }
+/// let streamed = builder.build();
+///
+/// let items: Vec<NameItem> = names.iter().map(|&name| NameItem::new(name)).collect();
+/// let batch = Vocabulary::new(&items);
+/// assert_eq!(streamed.get_chars(), batch.get_chars());
+/// assert_eq!(streamed.get_char_to_idx(), batch.get_char_to_idx());
+/// assert_eq!(streamed.get_total_occurrences(), batch.get_total_occurrences());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct VocabularyBuilder {
+    /// Tokens seen so far, in no particular order
+    chars: HashSet<String>,
+    /// Total number of characters in the names added so far
+    total_occurrences: usize,
+    /// Splits added names into tokens
+    tokenizer: Tokenizer,
+}
+
+impl VocabularyBuilder {
+    /// Creates an empty builder that splits names into single characters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the tokenizer used to split added names, as in [`Vocabulary::with_tokenizer`].
+    ///
+    /// Names added before the tokenizer is set keep the tokens they were split into.
+    pub fn with_tokenizer(mut self, tokenizer: Tokenizer) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// Adds the tokens of `name` to the vocabulary being built.
+    pub fn add_name(&mut self, name: &str) {
+        self.chars.extend(self.tokenizer.tokenize(name));
+        self.total_occurrences += name.chars().count();
+    }
+
+    /// Sorts the collected tokens alphabetically with "." first and builds the vocabulary.
+    pub fn build(self) -> Vocabulary {
+        let chars = Vocabulary::sort_chars(self.chars);
+        let char_to_idx = chars
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.clone(), i))
+            .collect();
+
+        Vocabulary {
+            chars,
+            char_to_idx,
+            total_occurrences: self.total_occurrences,
+            unk_index: None,
+            tokenizer: self.tokenizer,
+        }
+    }
 }
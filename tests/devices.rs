@@ -0,0 +1,27 @@
+//! Checks that GPU builds actually find and use their device.
+//!
+//! These tests need real hardware, so they only exist with the `cuda` or `metal` feature
+//! and are ignored by default. Run them with `cargo test --features cuda -- --ignored`
+//! (or `--features metal`).
+#![cfg(any(feature = "cuda", feature = "metal"))]
+
+use candle_core::{DType, Tensor};
+use makemore_rs::utils::available_devices;
+
+#[test]
+#[ignore = "requires a CUDA or Metal device"]
+fn gpu_device_is_available_and_allocates() {
+    let devices = available_devices();
+    let gpu = devices
+        .iter()
+        .find(|device| !device.is_cpu())
+        .expect("No GPU device was returned");
+
+    let tensor = Tensor::ones((2, 3), DType::F32, gpu).expect("Failed to allocate on the GPU");
+    assert!(tensor.device().same_device(gpu));
+    let sum = tensor
+        .sum_all()
+        .and_then(|sum| sum.to_scalar::<f32>())
+        .expect("Failed to read the tensor back");
+    assert_eq!(sum, 6.0);
+}
@@ -0,0 +1,52 @@
+//! Runs the `makemore-rs` binary to check that it honours its environment variables.
+
+use std::process::Command;
+
+/// Runs the binary from the crate root, where it finds `names.txt`, and returns stdout.
+fn run_main(steps: &str, samples: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_makemore-rs"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("MAKEMORE_STEPS", steps)
+        .env("MAKEMORE_SAMPLES", samples)
+        .env("MAKEMORE_SEED", "42")
+        .output()
+        .expect("Failed to run makemore-rs");
+    assert!(
+        output.status.success(),
+        "makemore-rs failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).expect("stdout is not UTF-8")
+}
+
+fn count_lines(stdout: &str, prefix: &str) -> usize {
+    stdout
+        .lines()
+        .filter(|line| line.starts_with(prefix))
+        .count()
+}
+
+#[test]
+fn samples_env_var_sets_number_of_generated_names() {
+    let stdout = run_main("1", "3");
+    assert_eq!(count_lines(&stdout, "Generated:"), 3);
+
+    let stdout = run_main("1", "7");
+    assert_eq!(count_lines(&stdout, "Generated:"), 7);
+}
+
+#[test]
+fn steps_env_var_sets_number_of_training_steps() {
+    let stdout = run_main("2", "1");
+    assert_eq!(count_lines(&stdout, "Step "), 2);
+}
+
+#[test]
+fn invalid_env_var_fails() {
+    let output = Command::new(env!("CARGO_BIN_EXE_makemore-rs"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("MAKEMORE_SAMPLES", "many")
+        .output()
+        .expect("Failed to run makemore-rs");
+    assert!(!output.status.success());
+}